@@ -0,0 +1,150 @@
+use std::fmt;
+
+use crate::instructions::schedule::{BusAccess, BusOperation};
+use crate::power::RamFill;
+use crate::{Byte, Byte2};
+
+/// Address space the CPU reads and writes through
+///
+/// Reads may have side effects on memory-mapped devices, such as acknowledging an interrupt or
+/// advancing a register pointer. `peek` returns what a read would without any of them, for
+/// debuggers, disassemblers and the CPU looking ahead at operands.
+pub trait Bus {
+    fn read(&mut self, address: Byte2) -> Byte;
+
+    fn write(&mut self, address: Byte2, value: Byte);
+
+    fn peek(&self, address: Byte2) -> Byte;
+
+    /// Called by the CPU once per cycle, for devices that keep time
+    fn tick(&mut self) {}
+
+    /// Whether a device on the bus holds the IRQ line asserted, in addition to
+    /// [`Cpu::set_irq`](crate::cpu::Cpu::set_irq)
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+/// Size of the 6502 address space
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// Flat 64 KiB of RAM covering the whole address space, with no devices mapped
+#[derive(Clone, PartialEq, Eq)]
+pub struct Memory(Box<[Byte; ADDRESS_SPACE]>);
+
+impl Memory {
+    /// Creates RAM filled with zeros
+    pub fn new() -> Self {
+        Memory(vec![0; ADDRESS_SPACE].into_boxed_slice().try_into().unwrap())
+    }
+
+    pub fn with_fill(fill: RamFill) -> Self {
+        let mut memory = Memory::new();
+        fill.fill(memory.0.as_mut_slice());
+        memory
+    }
+
+    /// Copies `bytes` into RAM from `address`, wrapping around to $0000 at the end
+    pub fn load(&mut self, address: Byte2, bytes: &[Byte]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.0[(address as usize + offset) % ADDRESS_SPACE] = *byte;
+        }
+    }
+
+    pub fn as_slice(&self) -> &[Byte] {
+        self.0.as_slice()
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memory").finish_non_exhaustive()
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: Byte2) -> Byte {
+        self.0[address as usize]
+    }
+
+    fn write(&mut self, address: Byte2, value: Byte) {
+        self.0[address as usize] = value;
+    }
+
+    fn peek(&self, address: Byte2) -> Byte {
+        self.0[address as usize]
+    }
+}
+
+/// Bus whose reads are peeks and whose writes are dropped, for working out what an instruction
+/// will do without disturbing any device
+pub struct PeekBus<'a, B: Bus + ?Sized>(pub &'a B);
+
+impl<B: Bus + ?Sized> Bus for PeekBus<'_, B> {
+    fn read(&mut self, address: Byte2) -> Byte {
+        self.0.peek(address)
+    }
+
+    fn write(&mut self, _address: Byte2, _value: Byte) {}
+
+    fn peek(&self, address: Byte2) -> Byte {
+        self.0.peek(address)
+    }
+}
+
+/// Bus replaying the accesses a cycle-stepped instruction has already made, so its handler sees
+/// the values read at the time without reading any device a second time
+///
+/// Writes are collected instead of performed, the CPU makes the final one on the real bus.
+pub(crate) struct ReplayBus {
+    accesses: Vec<BusAccess>,
+    writes: Vec<(Byte2, Byte)>,
+}
+
+impl ReplayBus {
+    pub(crate) fn new(accesses: Vec<BusAccess>) -> Self {
+        ReplayBus {
+            accesses,
+            writes: Vec::new(),
+        }
+    }
+
+    /// Address and value of the last write the handler made
+    pub(crate) fn last_write(&self) -> Option<(Byte2, Byte)> {
+        self.writes.last().copied()
+    }
+}
+
+impl Bus for ReplayBus {
+    fn read(&mut self, address: Byte2) -> Byte {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: Byte2, value: Byte) {
+        self.writes.push((address, value));
+    }
+
+    fn peek(&self, address: Byte2) -> Byte {
+        let written = self.writes.iter().rev().find(|(written, _)| *written == address);
+        if let Some((_, value)) = written {
+            return *value;
+        }
+        // Every location a handler reads was read by the schedule, fall back to the last value
+        // seen on the bus like an open bus would
+        let read = self.accesses.iter().rev().find(|access| {
+            access.address == address
+                && matches!(access.operation, BusOperation::Read | BusOperation::DummyRead)
+        });
+        match read {
+            Some(access) => access.value,
+            None => self.accesses.last().map_or(0, |access| access.value),
+        }
+    }
+}
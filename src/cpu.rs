@@ -0,0 +1,916 @@
+use crate::bus::{Bus, Memory, PeekBus, ReplayBus};
+use crate::instructions::cycles::additional_cycles;
+use crate::instructions::opcode::{
+    execute, interrupt, read_vector, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR,
+};
+use crate::instructions::parser::{is_undocumented_opcode, DecodeError};
+use crate::instructions::schedule::{
+    instruction_schedule, interrupt_schedule, BusAccess, BusOperation,
+};
+use crate::instructions::stack::StackMonitor;
+use crate::{read_opcode, Byte, Byte2, Instruction, Opcode, PushSource, Registers};
+
+/// Cycles taken by the RESET, NMI and IRQ sequences
+const INTERRUPT_CYCLES: Byte = 7;
+
+/// Hardware interrupt the CPU has committed to service before the next instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Non-maskable interrupt, latched on the falling edge of the NMI line
+    Nmi,
+    /// Maskable interrupt, serviced while the IRQ line is held and I is clear
+    Irq,
+}
+
+/// What the CPU does when the byte at the program counter is not a known opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownOpcodePolicy {
+    /// Stop executing until the next reset, every step reports the error again
+    #[default]
+    Halt,
+    /// Skip the byte as if it were a one-byte, two-cycle NOP
+    Nop,
+    /// Report the error without changing any state so a debugger can take over
+    Trap,
+}
+
+/// How [`Cpu::step`] advances the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionMode {
+    /// Executes whole instructions at once
+    #[default]
+    Instruction,
+    /// Advances cycle by cycle as [`Cpu::tick`] does, making every bus access in hardware order
+    /// including dummy reads and writes
+    Cycle,
+}
+
+/// Work the cycle-stepped CPU is in the middle of
+enum Operation {
+    Instruction(Instruction),
+    /// Unknown opcode stepped over under [`UnknownOpcodePolicy::Nop`]
+    SkippedOpcode,
+    Interrupt(Interrupt),
+}
+
+struct InFlight {
+    operation: Operation,
+    accesses: Vec<BusAccess>,
+    /// Index of the access made on the next cycle
+    next: usize,
+    /// An NMI took over the vector fetch of this BRK or IRQ
+    hijacked: bool,
+}
+
+impl InFlight {
+    /// Points the vector fetch of a BRK or IRQ made on cycle `index` at the NMI vector, as an
+    /// NMI detected while the frame is being pushed does
+    fn hijack_vector_fetch(&mut self, index: usize) -> bool {
+        let hijackable = match &self.operation {
+            Operation::Instruction(instruction) => instruction.opcode == Opcode::BRK,
+            Operation::Interrupt(interrupt) => *interrupt == Interrupt::Irq,
+            Operation::SkippedOpcode => false,
+        };
+        // The vector is fetched on the last two cycles
+        let access = self.accesses[index];
+        if !hijackable || index + 2 != self.accesses.len() || access.address != IRQ_VECTOR {
+            return false;
+        }
+        self.accesses[index].address = NMI_VECTOR;
+        self.accesses[index + 1].address = NMI_VECTOR.wrapping_add(1);
+        self.hijacked = true;
+        true
+    }
+}
+
+/// Processor model the core emulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CpuVariant {
+    /// NES CPU, an NMOS 6502 whose ADC and SBC ignore the decimal flag
+    #[default]
+    Ricoh2A03,
+    /// NMOS 6502 with BCD arithmetic and the undocumented opcodes
+    Nmos6502,
+    /// WDC 65C02 with the CMOS opcode set and bug fixes
+    Wdc65C02,
+}
+
+impl CpuVariant {
+    /// Whether ADC and SBC honour the decimal flag
+    pub fn has_decimal_mode(&self) -> bool {
+        !matches!(self, CpuVariant::Ricoh2A03)
+    }
+
+    pub fn is_cmos(&self) -> bool {
+        matches!(self, CpuVariant::Wdc65C02)
+    }
+}
+
+/// 6502 CPU owning its registers and the bus it executes from
+pub struct Cpu<B: Bus = Memory> {
+    variant: CpuVariant,
+    registers: Registers,
+    bus: B,
+    /// Cycles executed since the CPU was created
+    cycles: u64,
+    /// Current level of the NMI input, asserted when `true`
+    nmi_line: bool,
+    /// An NMI edge has been detected and not yet serviced
+    nmi_pending: bool,
+    /// Current level of the IRQ input, asserted when `true`
+    irq_line: bool,
+    /// Result of the interrupt poll at the end of the last instruction
+    pending_interrupt: Option<Interrupt>,
+    /// Optional stack diagnostics for debugging tools
+    stack_monitor: Option<StackMonitor>,
+    execution_mode: ExecutionMode,
+    /// Instruction or interrupt being executed in [`ExecutionMode::Cycle`]
+    in_flight: Option<InFlight>,
+    /// Accesses of the instruction being committed in [`ExecutionMode::Cycle`], which its handler
+    /// runs against instead of the bus
+    replay: Option<ReplayBus>,
+    /// Receives every bus access made in [`ExecutionMode::Cycle`]
+    bus_listener: Option<Box<dyn FnMut(BusAccess)>>,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+    /// Whether undocumented NMOS opcodes execute or are treated as unknown
+    undocumented_opcodes: bool,
+    /// WAI is holding the CPU until an interrupt line is asserted
+    waiting: bool,
+    /// Set when execution stopped on a JAM, an STP or an unknown opcode under [`UnknownOpcodePolicy::Halt`]
+    halted: Option<DecodeError>,
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Creates a CPU with cleared registers starting execution at `program_counter`
+    pub fn new(bus: B, program_counter: Byte2) -> Self {
+        Cpu {
+            variant: CpuVariant::default(),
+            registers: Registers::new(program_counter),
+            bus,
+            cycles: 0,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+            pending_interrupt: None,
+            stack_monitor: None,
+            execution_mode: ExecutionMode::default(),
+            in_flight: None,
+            replay: None,
+            bus_listener: None,
+            unknown_opcode_policy: UnknownOpcodePolicy::default(),
+            undocumented_opcodes: true,
+            waiting: false,
+            halted: None,
+        }
+    }
+
+    /// Powers the CPU up on `bus` and runs the reset sequence, RAM holds whatever the bus was
+    /// filled with
+    pub fn power_on(mut bus: B) -> Self {
+        let program_counter = read_vector(RESET_VECTOR, &mut bus);
+        let mut cpu = Cpu::new(bus, program_counter);
+        cpu.registers = Registers::power_on(program_counter);
+        cpu.advance(INTERRUPT_CYCLES as u64);
+        cpu
+    }
+
+    pub fn variant(&self) -> CpuVariant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: CpuVariant) {
+        self.variant = variant;
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Total number of cycles executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Installs or removes the monitor reporting stack diagnostics
+    pub fn set_stack_monitor(&mut self, stack_monitor: Option<StackMonitor>) {
+        self.stack_monitor = stack_monitor;
+    }
+
+    /// Switches between instruction and cycle stepping, an instruction in progress finishes
+    /// cycle by cycle first
+    pub fn set_execution_mode(&mut self, execution_mode: ExecutionMode) {
+        self.execution_mode = execution_mode;
+    }
+
+    /// Installs or removes the listener receiving each bus access in [`ExecutionMode::Cycle`]
+    pub fn set_bus_listener(&mut self, bus_listener: Option<Box<dyn FnMut(BusAccess)>>) {
+        self.bus_listener = bus_listener;
+    }
+
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownOpcodePolicy) {
+        self.unknown_opcode_policy = policy;
+    }
+
+    /// Enables or disables the undocumented NMOS opcodes, disabled ones go through the unknown
+    /// opcode policy
+    ///
+    /// Has no effect on the 65C02, which has no undocumented opcodes.
+    pub fn set_undocumented_opcodes(&mut self, enabled: bool) {
+        self.undocumented_opcodes = enabled;
+    }
+
+    /// Whether the CPU has stopped on a JAM, an STP or an unknown opcode and waits for a reset
+    pub fn is_halted(&self) -> bool {
+        self.halted.is_some()
+    }
+
+    /// Drives the NMI input, an NMI is latched when the line becomes asserted
+    pub fn set_nmi(&mut self, active: bool) {
+        if active && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = active;
+    }
+
+    /// Drives the IRQ input, which stays asserted until the source acknowledges it
+    pub fn set_irq(&mut self, active: bool) {
+        self.irq_line = active;
+    }
+
+    /// Soft reset, as the console's reset button does: runs the reset sequence and loads the
+    /// program counter from $FFFC, leaving RAM and A, X and Y alone
+    pub fn reset(&mut self) {
+        // Reset goes through the interrupt sequence with the pushes turned into reads
+        self.registers.s = self.registers.s.wrapping_sub(3);
+        self.registers.status.replace_i(true);
+        if self.variant.is_cmos() {
+            self.registers.status.replace_d(false);
+        }
+        self.registers.p = read_vector(RESET_VECTOR, &mut self.bus);
+        self.nmi_pending = false;
+        self.pending_interrupt = None;
+        self.in_flight = None;
+        self.waiting = false;
+        self.halted = None;
+        self.advance(INTERRUPT_CYCLES as u64);
+    }
+
+    /// Executes the instruction at the program counter or services a pending interrupt,
+    /// returning the cycles it took
+    ///
+    /// In [`ExecutionMode::Cycle`] this ticks until the current instruction has finished.
+    pub fn step(&mut self) -> Result<Byte, DecodeError> {
+        match self.execution_mode {
+            ExecutionMode::Instruction if self.in_flight.is_none() => self.step_instruction(),
+            // An instruction left in progress by cycle stepping finishes cycle by cycle first
+            _ => {
+                let mut cycles = 1;
+                while !self.tick()? {
+                    cycles += 1;
+                }
+                Ok(cycles)
+            }
+        }
+    }
+
+    fn step_instruction(&mut self) -> Result<Byte, DecodeError> {
+        if let Some(error) = &self.halted {
+            return Err(error.clone());
+        }
+        if self.is_waiting() {
+            self.advance(1);
+            return Ok(1);
+        }
+        let cycles = match self.pending_interrupt.take() {
+            Some(interrupt) => self.service_interrupt(interrupt),
+            None => match self.decode_instruction(true)? {
+                Some(instruction) => self.execute_instruction(instruction),
+                None => self.skip_opcode(),
+            },
+        };
+        self.advance(cycles as u64);
+        Ok(cycles)
+    }
+
+    /// Advances the CPU by a single cycle, making the bus access of that cycle
+    ///
+    /// Returns whether an instruction or interrupt sequence finished on this cycle. The
+    /// instruction takes effect on its last cycle.
+    pub fn tick(&mut self) -> Result<bool, DecodeError> {
+        let mut in_flight = match self.in_flight.take() {
+            Some(in_flight) => in_flight,
+            None => {
+                if let Some(error) = &self.halted {
+                    return Err(error.clone());
+                }
+                if self.is_waiting() {
+                    self.advance(1);
+                    return Ok(true);
+                }
+                self.begin_operation()?
+            }
+        };
+        let index = in_flight.next;
+        if self.nmi_pending && in_flight.hijack_vector_fetch(index) {
+            self.nmi_pending = false;
+        }
+        let access = in_flight.accesses[index];
+        in_flight.next += 1;
+        self.advance(1);
+        if in_flight.next < in_flight.accesses.len() {
+            in_flight.accesses[index] = self.access_bus(access);
+            self.in_flight = Some(in_flight);
+            return Ok(false);
+        }
+        // The value of a final write is only known once the instruction has executed
+        let is_write = access.operation == BusOperation::Write;
+        if !is_write {
+            in_flight.accesses[index] = self.access_bus(access);
+        }
+        // The handler sees the values read on earlier cycles rather than reading devices again
+        self.replay = Some(ReplayBus::new(in_flight.accesses));
+        let hijacked = in_flight.hijacked;
+        match in_flight.operation {
+            Operation::Instruction(instruction) => {
+                self.execute_instruction(instruction);
+            }
+            Operation::SkippedOpcode => {
+                self.skip_opcode();
+            }
+            Operation::Interrupt(interrupt) => {
+                self.service_interrupt(interrupt);
+            }
+        }
+        let mut replay = self.replay.take().unwrap();
+        if hijacked {
+            // The handler fetched the IRQ vector, the bus delivered the NMI vector
+            self.registers.p = read_vector(NMI_VECTOR, &mut replay);
+        }
+        if is_write {
+            let (address, value) = replay.last_write().unwrap_or((access.address, access.value));
+            self.access_bus(BusAccess { address, value, ..access });
+        }
+        Ok(true)
+    }
+
+    /// Counts elapsed cycles and lets the devices on the bus run for them
+    fn advance(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        for _ in 0..cycles {
+            self.bus.tick();
+        }
+    }
+
+    /// Whether WAI still holds the CPU, asserting either interrupt line releases it
+    fn is_waiting(&mut self) -> bool {
+        if !self.waiting {
+            return false;
+        }
+        if !self.nmi_pending && !self.irq_asserted() {
+            return true;
+        }
+        // A masked IRQ still ends the wait, execution then resumes after WAI
+        self.waiting = false;
+        self.poll_interrupts(self.registers.status.get_i());
+        false
+    }
+
+    /// Picks the next instruction or interrupt and lists its bus accesses
+    fn begin_operation(&mut self) -> Result<InFlight, DecodeError> {
+        let (operation, accesses) = match self.pending_interrupt.take() {
+            Some(interrupt) => {
+                let accesses = interrupt_schedule(interrupt, &self.registers, &self.bus);
+                (Operation::Interrupt(interrupt), accesses)
+            }
+            None => match self.decode_instruction(false)? {
+                Some(instruction) => {
+                    let accesses = self.schedule_instruction(&instruction);
+                    (Operation::Instruction(instruction), accesses)
+                }
+                None => {
+                    let accesses = self.schedule_instruction(&Instruction::skipped_opcode());
+                    (Operation::SkippedOpcode, accesses)
+                }
+            },
+        };
+        Ok(InFlight {
+            operation,
+            accesses,
+            next: 0,
+            hijacked: false,
+        })
+    }
+
+    fn schedule_instruction(&mut self, instruction: &Instruction) -> Vec<BusAccess> {
+        let cycles = self.instruction_cycles(instruction);
+        instruction_schedule(instruction, cycles, self.variant, &self.registers, &self.bus)
+    }
+
+    /// Makes a scheduled access on the bus, returning it with the value actually transferred
+    fn access_bus(&mut self, access: BusAccess) -> BusAccess {
+        let value = match access.operation {
+            BusOperation::Read | BusOperation::DummyRead => self.bus.read(access.address),
+            BusOperation::Write | BusOperation::DummyWrite => {
+                self.bus.write(access.address, access.value);
+                access.value
+            }
+        };
+        let access = BusAccess { value, ..access };
+        self.report_bus_access(access);
+        access
+    }
+
+    fn report_bus_access(&mut self, access: BusAccess) {
+        if let Some(bus_listener) = &mut self.bus_listener {
+            bus_listener(access);
+        }
+    }
+
+    /// Decodes the instruction at the program counter, `None` when an unknown opcode is to be
+    /// skipped
+    ///
+    /// Without `fetch` the bytes are only peeked, the cycle-stepped CPU reads them on their own
+    /// cycles later.
+    fn decode_instruction(&mut self, fetch: bool) -> Result<Option<Instruction>, DecodeError> {
+        let address = self.registers.p;
+        let opcode = self.bus.peek(address);
+        let instruction = match fetch {
+            true => read_opcode(self.variant, &self.registers, &mut self.bus),
+            false => read_opcode(self.variant, &self.registers, &mut PeekBus(&self.bus)),
+        };
+        let instruction = match instruction {
+            Ok(instruction) => instruction,
+            Err(error) => return self.handle_unknown_opcode(error),
+        };
+        if !self.variant.is_cmos() && !self.undocumented_opcodes && is_undocumented_opcode(opcode) {
+            return self.handle_unknown_opcode(DecodeError::UndocumentedOpcode { opcode, address });
+        }
+        if matches!(instruction.opcode, Opcode::JAM | Opcode::STP) {
+            let error = DecodeError::Jammed { opcode, address };
+            self.halted = Some(error.clone());
+            return Err(error);
+        }
+        Ok(Some(instruction))
+    }
+
+    /// Cycles the instruction at the program counter takes including its penalties
+    fn instruction_cycles(&mut self, instruction: &Instruction) -> Byte {
+        let address = self.registers.p;
+        self.registers.p = address.wrapping_add(instruction.length as Byte2);
+        let cycles = instruction.cycles
+            + additional_cycles(self.variant, instruction, &self.registers, &self.bus);
+        self.registers.p = address;
+        cycles
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction) -> Byte {
+        let address = self.registers.p;
+        let stack_pointer = self.registers.s;
+        let cycles = self.instruction_cycles(&instruction);
+        self.registers.p = self.registers.p.wrapping_add(instruction.length as Byte2);
+        let interrupt_disable = self.registers.status.get_i();
+        let bus = handler_bus(&mut self.bus, &mut self.replay);
+        execute(&instruction, &mut self.registers, bus);
+        match instruction.opcode {
+            Opcode::BRK => self.enter_interrupt_handler(),
+            Opcode::WAI => self.waiting = true,
+            _ => {}
+        }
+        if let Some(stack_monitor) = &mut self.stack_monitor {
+            stack_monitor.observe_instruction(&instruction, address, stack_pointer, &self.registers);
+        }
+        // Interrupts are polled before the last cycle, so CLI, SEI and PLP only take effect after
+        // the next instruction while RTI restores I in time
+        let interrupt_disable = match instruction.opcode {
+            Opcode::RTI => self.registers.status.get_i(),
+            // BRK sets I as it enters the handler, which runs an instruction before any IRQ
+            Opcode::BRK => true,
+            _ => interrupt_disable,
+        };
+        self.poll_interrupts(interrupt_disable);
+        cycles
+    }
+
+    /// Steps over an unknown opcode as a one-byte NOP
+    fn skip_opcode(&mut self) -> Byte {
+        self.registers.p = self.registers.p.wrapping_add(1);
+        self.poll_interrupts(self.registers.status.get_i());
+        2
+    }
+
+    fn handle_unknown_opcode(&mut self, error: DecodeError) -> Result<Option<Instruction>, DecodeError> {
+        match self.unknown_opcode_policy {
+            UnknownOpcodePolicy::Halt => {
+                self.halted = Some(error.clone());
+                Err(error)
+            }
+            UnknownOpcodePolicy::Nop => Ok(None),
+            UnknownOpcodePolicy::Trap => Err(error),
+        }
+    }
+
+    fn service_interrupt(&mut self, pending: Interrupt) -> Byte {
+        if let Some(stack_monitor) = &mut self.stack_monitor {
+            stack_monitor.observe_interrupt(self.registers.p, self.registers.s);
+        }
+        let bus = handler_bus(&mut self.bus, &mut self.replay);
+        match pending {
+            Interrupt::Nmi => {
+                self.nmi_pending = false;
+                interrupt(NMI_VECTOR, PushSource::Nmi, &mut self.registers, bus);
+            }
+            Interrupt::Irq => {
+                interrupt(IRQ_VECTOR, PushSource::Irq, &mut self.registers, bus);
+            }
+        }
+        self.enter_interrupt_handler();
+        self.poll_interrupts(true);
+        INTERRUPT_CYCLES
+    }
+
+    /// Finishes BRK, IRQ and NMI once the frame has been pushed and the vector fetched
+    fn enter_interrupt_handler(&mut self) {
+        if self.variant.is_cmos() {
+            self.registers.status.replace_d(false);
+        }
+        // An NMI detected while BRK or IRQ is pushing its frame takes over the vector fetch, the
+        // cycle-stepped CPU decides on the cycle of the fetch instead
+        if self.replay.is_none() && self.nmi_pending {
+            self.nmi_pending = false;
+            self.registers.p = read_vector(NMI_VECTOR, &mut self.bus);
+        }
+    }
+
+    /// Level of the IRQ line, which the caller and the devices on the bus drive wired-OR
+    fn irq_asserted(&self) -> bool {
+        self.irq_line || self.bus.irq()
+    }
+
+    fn poll_interrupts(&mut self, interrupt_disable: bool) {
+        self.pending_interrupt = if self.nmi_pending {
+            Some(Interrupt::Nmi)
+        } else if self.irq_asserted() && !interrupt_disable {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        };
+    }
+}
+
+/// Bus an instruction or interrupt handler runs against, the replay while committing a
+/// cycle-stepped operation and the real bus otherwise
+fn handler_bus<'a, B: Bus>(bus: &'a mut B, replay: &'a mut Option<ReplayBus>) -> &'a mut dyn Bus {
+    match replay {
+        Some(replay) => replay,
+        None => bus,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::StatusFlags;
+
+    const NMI_HANDLER: Byte2 = 0x0800;
+    const IRQ_HANDLER: Byte2 = 0x0700;
+
+    /// CPU at $0600 running `program`, with NOPs at the NMI handler at $0800 and the IRQ handler
+    /// at $0700
+    fn cpu(program: &[Byte]) -> Cpu {
+        let mut memory = Memory::new();
+        memory.load(0x0600, program);
+        memory.load(NMI_VECTOR, &[0x00, 0x08, 0x00, 0x06, 0x00, 0x07]);
+        memory.load(IRQ_HANDLER, &[0xea]);
+        memory.load(NMI_HANDLER, &[0xea]);
+        Cpu::new(memory, 0x0600)
+    }
+
+    fn pushed_status(cpu: &Cpu) -> StatusFlags {
+        StatusFlags::from(cpu.bus().peek(0x0100 | cpu.registers().s.wrapping_add(1) as Byte2))
+    }
+
+    #[test]
+    fn reset_clears_decimal_mode_on_65c02_only() {
+        for (variant, decimal) in [(CpuVariant::Nmos6502, true), (CpuVariant::Wdc65C02, false)] {
+            let mut cpu = cpu(&[]);
+            cpu.set_variant(variant);
+            cpu.registers_mut().status.replace_d(true);
+            cpu.reset();
+            assert_eq!(cpu.registers().status.get_d(), decimal, "{variant:?}");
+            assert!(cpu.registers().status.get_i());
+            assert_eq!(cpu.registers().p, 0x0600);
+        }
+    }
+
+    #[test]
+    fn brk_with_irq_asserted_runs_an_instruction_of_the_handler_first() {
+        let mut cpu = cpu(&[0x00, 0x00]);
+        cpu.set_irq(true);
+        assert_eq!(cpu.step(), Ok(7));
+        assert_eq!((cpu.registers().p, cpu.registers().s), (IRQ_HANDLER, 0xfc));
+        assert_eq!(cpu.step(), Ok(2));
+        assert_eq!((cpu.registers().p, cpu.registers().s), (IRQ_HANDLER + 1, 0xfc));
+    }
+
+    #[test]
+    fn irq_is_taken_after_the_instruction_following_cli() {
+        let mut cpu = cpu(&[0x58, 0xea, 0xea]);
+        cpu.registers_mut().status.replace_i(true);
+        cpu.set_irq(true);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers().p, 0x0602);
+        assert_eq!(cpu.step(), Ok(7));
+        assert_eq!(cpu.registers().p, IRQ_HANDLER);
+        assert!(!pushed_status(&cpu).get_b());
+    }
+
+    /// CPU stopping on the undocumented NOP $1A with undocumented opcodes disabled
+    fn unknown_opcode_cpu(policy: UnknownOpcodePolicy) -> Cpu {
+        let mut cpu = cpu(&[0x1a, 0xea]);
+        cpu.set_undocumented_opcodes(false);
+        cpu.set_unknown_opcode_policy(policy);
+        cpu
+    }
+
+    const UNKNOWN: DecodeError = DecodeError::UndocumentedOpcode { opcode: 0x1a, address: 0x0600 };
+
+    #[test]
+    fn halt_policy_stops_until_reset() {
+        let mut cpu = unknown_opcode_cpu(UnknownOpcodePolicy::Halt);
+        assert_eq!(cpu.step(), Err(UNKNOWN));
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.step(), Err(UNKNOWN));
+        assert_eq!(cpu.registers().p, 0x0600);
+        cpu.reset();
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn nop_policy_skips_the_byte_in_two_cycles() {
+        let mut cpu = unknown_opcode_cpu(UnknownOpcodePolicy::Nop);
+        assert_eq!(cpu.step(), Ok(2));
+        assert_eq!(cpu.registers().p, 0x0601);
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn trap_policy_reports_the_byte_without_changing_state() {
+        let mut cpu = unknown_opcode_cpu(UnknownOpcodePolicy::Trap);
+        let registers = cpu.registers().clone();
+        assert_eq!(cpu.step(), Err(UNKNOWN));
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.registers(), &registers);
+        assert_eq!(cpu.cycles(), 0);
+        cpu.set_undocumented_opcodes(true);
+        assert_eq!(cpu.step(), Ok(2));
+    }
+
+    #[test]
+    fn nmi_is_serviced_before_irq() {
+        let mut cpu = cpu(&[0xea, 0xea]);
+        cpu.set_irq(true);
+        cpu.set_nmi(true);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers().p, NMI_HANDLER);
+        // The IRQ waits until the NMI handler clears I
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers().p, NMI_HANDLER + 1);
+    }
+
+    #[test]
+    fn nmi_during_brk_takes_over_its_vector() {
+        let mut cpu = cpu(&[0x00, 0x00]);
+        cpu.set_nmi(true);
+        cpu.step().unwrap();
+        assert_eq!((cpu.registers().p, cpu.registers().s), (NMI_HANDLER, 0xfc));
+        assert!(pushed_status(&cpu).get_b());
+        // The NMI was used up by the hijack
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers().p, NMI_HANDLER + 1);
+    }
+
+    /// RAM logging every read and write a device would see
+    struct RecordingBus {
+        memory: Memory,
+        log: Vec<(Byte2, Option<Byte>)>,
+    }
+
+    impl Bus for RecordingBus {
+        fn read(&mut self, address: Byte2) -> Byte {
+            self.log.push((address, None));
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: Byte2, value: Byte) {
+            self.log.push((address, Some(value)));
+            self.memory.write(address, value);
+        }
+
+        fn peek(&self, address: Byte2) -> Byte {
+            self.memory.peek(address)
+        }
+    }
+
+    /// Indexed reads across a page, stores, read-modify-writes, a subroutine and the stack
+    const PROGRAM: [Byte; 19] = [
+        0xa2, 0x01, // LDX #$01
+        0xbd, 0xff, 0x10, // LDA $10FF,X
+        0x85, 0x20, // STA $20
+        0xe6, 0x20, // INC $20
+        0x20, 0x10, 0x06, // JSR $0610
+        0x48, // PHA
+        0x68, // PLA
+        0xd0, 0x00, // BNE +0
+        0x06, 0x20, // $0610: ASL $20
+        0x60, // RTS
+    ];
+    const PROGRAM_STEPS: usize = 10;
+
+    type Listener = Rc<RefCell<Vec<BusAccess>>>;
+
+    /// Like [`cpu`] on a [`RecordingBus`], with a listener collecting the accesses reported
+    fn recording_cpu(
+        program: &[Byte],
+        variant: CpuVariant,
+        execution_mode: ExecutionMode,
+    ) -> (Cpu<RecordingBus>, Listener) {
+        let memory = cpu(program).bus;
+        let mut cpu = Cpu::new(RecordingBus { memory, log: Vec::new() }, 0x0600);
+        cpu.set_variant(variant);
+        cpu.set_execution_mode(execution_mode);
+        let accesses = Rc::new(RefCell::new(Vec::new()));
+        let listener = Rc::clone(&accesses);
+        cpu.set_bus_listener(Some(Box::new(move |access| listener.borrow_mut().push(access))));
+        (cpu, accesses)
+    }
+
+    #[test]
+    fn cycle_and_instruction_modes_agree() {
+        for variant in [CpuVariant::Ricoh2A03, CpuVariant::Nmos6502, CpuVariant::Wdc65C02] {
+            let (mut by_instruction, _) = recording_cpu(&PROGRAM, variant, ExecutionMode::Instruction);
+            let (mut by_cycle, accesses) = recording_cpu(&PROGRAM, variant, ExecutionMode::Cycle);
+            by_instruction.bus_mut().memory.load(0x1100, &[0x42]);
+            by_cycle.bus_mut().memory.load(0x1100, &[0x42]);
+            for _ in 0..PROGRAM_STEPS {
+                assert_eq!(by_instruction.step(), by_cycle.step(), "{variant:?}");
+                assert_eq!(by_instruction.registers(), by_cycle.registers(), "{variant:?}");
+            }
+            assert_eq!(by_instruction.registers().p, 0x0610, "{variant:?}");
+            assert_eq!(by_instruction.bus().memory, by_cycle.bus().memory, "{variant:?}");
+            assert_eq!(by_instruction.cycles(), by_cycle.cycles(), "{variant:?}");
+            // Every cycle makes exactly one access, each reported once and in order
+            let accesses = accesses.borrow();
+            let reported: Vec<_> = accesses
+                .iter()
+                .map(|access| match access.operation {
+                    BusOperation::Read | BusOperation::DummyRead => (access.address, None),
+                    BusOperation::Write | BusOperation::DummyWrite => (access.address, Some(access.value)),
+                })
+                .collect();
+            assert_eq!(reported, by_cycle.bus().log, "{variant:?}");
+            assert_eq!(accesses.len() as u64, by_cycle.cycles(), "{variant:?}");
+            // Both modes write the same values, cycle stepping adds the NMOS dummy writes
+            let writes = |log: &[(Byte2, Option<Byte>)]| log.iter().filter(|(_, value)| value.is_some()).count();
+            let dummy_writes = accesses.iter().filter(|access| access.operation == BusOperation::DummyWrite);
+            assert_eq!(
+                writes(&by_instruction.bus().log) + dummy_writes.count(),
+                writes(&by_cycle.bus().log),
+                "{variant:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cycle_mode_makes_the_dummy_read_of_a_page_crossing() {
+        let (mut cpu, accesses) = recording_cpu(&PROGRAM, CpuVariant::Nmos6502, ExecutionMode::Cycle);
+        cpu.bus_mut().memory.load(0x1100, &[0x42]);
+        cpu.step().unwrap();
+        accesses.borrow_mut().clear();
+        assert_eq!(cpu.step(), Ok(5));
+        let trace: Vec<_> = accesses.borrow().iter().map(|access| (access.operation, access.address)).collect();
+        assert_eq!(
+            trace,
+            [
+                (BusOperation::Read, 0x0602),
+                (BusOperation::Read, 0x0603),
+                (BusOperation::Read, 0x0604),
+                (BusOperation::DummyRead, 0x1000),
+                (BusOperation::Read, 0x1100),
+            ]
+        );
+        assert_eq!(cpu.registers().a, 0x42);
+    }
+
+    #[test]
+    fn cycle_mode_makes_the_real_access_of_every_65c02_cycle() {
+        // SED, ADC #$01, the one-cycle NOP $03, then the eight-cycle NOP $5C $34 $12
+        let program = [0xf8, 0x69, 0x01, 0x03, 0x5c, 0x34, 0x12];
+        let (mut cpu, accesses) = recording_cpu(&program, CpuVariant::Wdc65C02, ExecutionMode::Cycle);
+        cpu.step().unwrap();
+        let mut trace = |cycles| {
+            accesses.borrow_mut().clear();
+            assert_eq!(cpu.step(), Ok(cycles));
+            accesses.borrow().iter().map(|access| (access.operation, access.address)).collect::<Vec<_>>()
+        };
+        let (read, dummy_read) = (BusOperation::Read, BusOperation::DummyRead);
+        assert_eq!(trace(3), [(read, 0x0601), (read, 0x0602), (dummy_read, 0x0603)]);
+        assert_eq!(trace(1), [(read, 0x0603)]);
+        assert_eq!(
+            trace(8),
+            [
+                (read, 0x0604),
+                (read, 0x0605),
+                (read, 0x0606),
+                (dummy_read, 0xff34),
+                (dummy_read, 0xffff),
+                (dummy_read, 0xffff),
+                (dummy_read, 0xffff),
+                (dummy_read, 0xffff),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_opcode_makes_one_access_per_cycle() {
+        for variant in [CpuVariant::Ricoh2A03, CpuVariant::Nmos6502, CpuVariant::Wdc65C02] {
+            for opcode in 0..=Byte::MAX {
+                for decimal in [false, true] {
+                    let program = [opcode, 0xff, 0x02];
+                    let (mut by_instruction, _) = recording_cpu(&program, variant, ExecutionMode::Instruction);
+                    let (mut by_cycle, accesses) = recording_cpu(&program, variant, ExecutionMode::Cycle);
+                    for cpu in [&mut by_instruction, &mut by_cycle] {
+                        cpu.registers_mut().x = 0x01;
+                        cpu.registers_mut().status.replace_d(decimal);
+                    }
+                    let cycles = by_cycle.step();
+                    assert_eq!(by_instruction.step(), cycles, "{variant:?} ${opcode:02X}");
+                    if let Ok(cycles) = cycles {
+                        assert_eq!(accesses.borrow().len(), cycles as usize, "{variant:?} ${opcode:02X}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn switching_to_instruction_mode_finishes_the_instruction_in_progress() {
+        let mut cpu = cpu(&[0xe6, 0x10, 0xea]);
+        cpu.set_execution_mode(ExecutionMode::Cycle);
+        assert_eq!(cpu.tick(), Ok(false));
+        cpu.set_execution_mode(ExecutionMode::Instruction);
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!((cpu.bus().peek(0x10), cpu.registers().p, cpu.cycles()), (1, 0x0602, 5));
+        cpu.set_execution_mode(ExecutionMode::Cycle);
+        assert_eq!(cpu.step(), Ok(2));
+        assert_eq!((cpu.bus().peek(0x10), cpu.registers().p, cpu.cycles()), (1, 0x0603, 7));
+    }
+
+    /// Vector fetch of a cycle-stepped BRK or IRQ when an NMI arrives after `ticks` cycles
+    fn hijack(program: &[Byte], irq: bool, ticks: usize) -> (Byte2, Vec<Byte2>) {
+        let (mut cpu, accesses) = recording_cpu(program, CpuVariant::default(), ExecutionMode::Cycle);
+        if irq {
+            cpu.set_irq(true);
+            cpu.step().unwrap();
+            accesses.borrow_mut().clear();
+            cpu.bus_mut().log.clear();
+        }
+        for _ in 0..ticks {
+            assert_eq!(cpu.tick(), Ok(false));
+        }
+        cpu.set_nmi(true);
+        cpu.step().unwrap();
+        let vector_reads = accesses.borrow().iter().skip(5).map(|access| access.address).collect();
+        assert_eq!(accesses.borrow().len(), 7);
+        assert_eq!(cpu.bus().log.iter().skip(5).map(|(address, _)| *address).collect::<Vec<_>>(), vector_reads);
+        (cpu.registers().p, vector_reads)
+    }
+
+    #[test]
+    fn nmi_before_the_vector_fetch_hijacks_a_cycle_stepped_brk_or_irq() {
+        assert_eq!(hijack(&[0x00, 0x00], false, 5), (NMI_HANDLER, vec![0xfffa, 0xfffb]));
+        assert_eq!(hijack(&[0xea], true, 3), (NMI_HANDLER, vec![0xfffa, 0xfffb]));
+    }
+
+    #[test]
+    fn nmi_after_the_vector_fetch_waits_for_the_next_instruction() {
+        assert_eq!(hijack(&[0x00, 0x00], false, 6), (IRQ_HANDLER, vec![0xfffe, 0xffff]));
+        assert_eq!(hijack(&[0xea], true, 6), (IRQ_HANDLER, vec![0xfffe, 0xffff]));
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+use crate::cpu::CpuVariant;
+use crate::instructions::metadata::instruction_info;
+use crate::bus::Bus;
+use crate::{AddressingModeType, Byte, Byte2};
+
+/// One disassembled instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    pub address: Byte2,
+    /// Opcode and operand bytes
+    pub bytes: Vec<Byte>,
+    /// Assembly text, e.g. `LDA ($20),Y`, or `.byte $02` for a byte that does not decode
+    pub text: String,
+}
+
+/// Formats the instruction as `C000  4C F5 C5  JMP $C5F5`
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        write!(f, "{:04X}  {:<8}  {}", self.address, bytes.join(" "), self.text)
+    }
+}
+
+/// Disassembles the instruction at `address`, peeking so no device sees a read
+pub fn disassemble<B: Bus + ?Sized>(variant: CpuVariant, bus: &B, address: Byte2) -> Disassembly {
+    let opcode = bus.peek(address);
+    let Some(info) = instruction_info(variant, opcode) else {
+        return Disassembly {
+            address,
+            bytes: vec![opcode],
+            text: format!(".byte ${opcode:02X}"),
+        };
+    };
+    let bytes: Vec<Byte> = (0..=info.operand_bytes as Byte2)
+        .map(|offset| bus.peek(address.wrapping_add(offset)))
+        .collect();
+    let next = address.wrapping_add(bytes.len() as Byte2);
+    let byte = bytes.get(1).copied().unwrap_or_default();
+    let word = ((bytes.get(2).copied().unwrap_or_default() as Byte2) << 8) | byte as Byte2;
+    let branch_target = |offset: Byte| next.wrapping_add(offset as i8 as Byte2);
+    let operand = match info.addressing_mode {
+        AddressingModeType::Implied => String::new(),
+        AddressingModeType::Accumulator => "A".to_string(),
+        AddressingModeType::Immediate => format!("#${byte:02X}"),
+        AddressingModeType::ZeroPage => format!("${byte:02X}"),
+        AddressingModeType::ZeroPageIndexedWithX => format!("${byte:02X},X"),
+        AddressingModeType::ZeroPageIndexedWithY => format!("${byte:02X},Y"),
+        AddressingModeType::Absolute => format!("${word:04X}"),
+        AddressingModeType::AbsoluteIndexedWithX => format!("${word:04X},X"),
+        AddressingModeType::AbsoluteIndexedWithY => format!("${word:04X},Y"),
+        AddressingModeType::AbsoluteIndirect => format!("(${word:04X})"),
+        AddressingModeType::AbsoluteIndexedIndirect => format!("(${word:04X},X)"),
+        AddressingModeType::IndexedIndirect => format!("(${byte:02X},X)"),
+        AddressingModeType::IndirectIndexed => format!("(${byte:02X}),Y"),
+        AddressingModeType::ZeroPageIndirect => format!("(${byte:02X})"),
+        AddressingModeType::Relative => format!("${:04X}", branch_target(byte)),
+        AddressingModeType::ZeroPageRelative => {
+            format!("${byte:02X},${:04X}", branch_target(bytes[2]))
+        }
+    };
+    let text = match operand.is_empty() {
+        true => info.mnemonic.to_string(),
+        false => format!("{} {operand}", info.mnemonic),
+    };
+    Disassembly { address, bytes, text }
+}
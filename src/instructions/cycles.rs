@@ -0,0 +1,154 @@
+use crate::bus::{Bus, PeekBus};
+use crate::cpu::CpuVariant;
+use crate::instructions::opcode::{bit_branch_condition, branch_condition};
+use crate::{get_address_from_relative, AddressingModeType, AddressingValue, Byte, Instruction, Opcode, Registers};
+
+/// Number of cycles an instruction takes before any page crossing or branch penalty
+pub fn base_cycles(opcode: Opcode, addressing_mode: AddressingModeType) -> Byte {
+    match opcode {
+        Opcode::BRK => 7,
+        Opcode::JSR | Opcode::RTI | Opcode::RTS => 6,
+        Opcode::PHA | Opcode::PHP | Opcode::PHX | Opcode::PHY => 3,
+        Opcode::PLA | Opcode::PLP | Opcode::PLX | Opcode::PLY => 4,
+        Opcode::JMP => 3,
+        Opcode::JMP_ABS => 5,
+        Opcode::ASL
+        | Opcode::LSR
+        | Opcode::ROL
+        | Opcode::ROR
+        | Opcode::INC
+        | Opcode::DEC
+        | Opcode::SLO
+        | Opcode::RLA
+        | Opcode::SRE
+        | Opcode::RRA
+        | Opcode::DCP
+        | Opcode::ISC
+        | Opcode::TRB
+        | Opcode::TSB
+        | Opcode::RMB(_)
+        | Opcode::SMB(_) => match addressing_mode {
+            AddressingModeType::Accumulator => 2,
+            AddressingModeType::ZeroPage => 5,
+            AddressingModeType::ZeroPageIndexedWithX | AddressingModeType::Absolute => 6,
+            AddressingModeType::IndexedIndirect | AddressingModeType::IndirectIndexed => 8,
+            _ => 7,
+        },
+        Opcode::STA
+        | Opcode::STX
+        | Opcode::STY
+        | Opcode::SAX
+        | Opcode::SHA
+        | Opcode::SHX
+        | Opcode::SHY
+        | Opcode::TAS
+        | Opcode::STZ => match addressing_mode {
+            // Stores always spend the cycle a read would only take on a page crossing
+            AddressingModeType::AbsoluteIndexedWithX | AddressingModeType::AbsoluteIndexedWithY => 5,
+            AddressingModeType::IndirectIndexed => 6,
+            mode => read_cycles(mode),
+        },
+        _ => read_cycles(addressing_mode),
+    }
+}
+
+/// Number of cycles an instruction takes on the WDC 65C02 before any page crossing, branch or
+/// decimal mode penalty
+pub fn base_cycles_65c02(opcode_byte: Byte, opcode: Opcode, addressing_mode: AddressingModeType) -> Byte {
+    match (opcode, addressing_mode) {
+        // The NOPs in the unused xxxx_x011 slots finish in a single cycle
+        (Opcode::NOP, AddressingModeType::Implied) if opcode_byte != 0xEA => 1,
+        (Opcode::NOP, AddressingModeType::Absolute) if opcode_byte == 0x5C => 8,
+        (Opcode::JMP_ABS, _) => 6,
+        // Shifts only spend the extra indexing cycle when crossing a page
+        (
+            Opcode::ASL | Opcode::LSR | Opcode::ROL | Opcode::ROR,
+            AddressingModeType::AbsoluteIndexedWithX,
+        ) => 6,
+        (Opcode::BBR(_) | Opcode::BBS(_), _) => 5,
+        (Opcode::WAI | Opcode::STP, _) => 3,
+        _ => base_cycles(opcode, addressing_mode),
+    }
+}
+
+fn read_cycles(addressing_mode: AddressingModeType) -> Byte {
+    match addressing_mode {
+        AddressingModeType::Accumulator
+        | AddressingModeType::Implied
+        | AddressingModeType::Immediate
+        | AddressingModeType::Relative => 2,
+        AddressingModeType::ZeroPage => 3,
+        AddressingModeType::ZeroPageIndexedWithX
+        | AddressingModeType::ZeroPageIndexedWithY
+        | AddressingModeType::Absolute
+        | AddressingModeType::AbsoluteIndexedWithX
+        | AddressingModeType::AbsoluteIndexedWithY => 4,
+        AddressingModeType::AbsoluteIndirect
+        | AddressingModeType::IndirectIndexed
+        | AddressingModeType::ZeroPageIndirect
+        | AddressingModeType::ZeroPageRelative => 5,
+        AddressingModeType::IndexedIndirect | AddressingModeType::AbsoluteIndexedIndirect => 6,
+    }
+}
+
+/// Whether an indexed read takes an extra cycle when the index crosses a page
+fn has_page_crossing_penalty(variant: CpuVariant, opcode: Opcode) -> bool {
+    if matches!(opcode, Opcode::ASL | Opcode::LSR | Opcode::ROL | Opcode::ROR) {
+        return variant.is_cmos();
+    }
+    matches!(
+        opcode,
+        Opcode::ADC
+            | Opcode::AND
+            | Opcode::BIT
+            | Opcode::CMP
+            | Opcode::EOR
+            | Opcode::LDA
+            | Opcode::LDX
+            | Opcode::LDY
+            | Opcode::ORA
+            | Opcode::SBC
+            | Opcode::LAX
+            | Opcode::LAS
+            | Opcode::NOP
+    )
+}
+
+/// Extra cycles for page crossings on indexed reads, for taken branches and for decimal mode on
+/// the 65C02
+///
+/// Must be called after the program counter has stepped over the instruction but before it is
+/// executed. Operands and pointers are peeked, so devices on the bus see no extra reads.
+pub fn additional_cycles(
+    variant: CpuVariant,
+    instruction: &Instruction,
+    registers: &Registers,
+    bus: &dyn Bus,
+) -> Byte {
+    let bus = &mut PeekBus(bus);
+    if let AddressingValue::ZeroPageRelative(_, offset) = instruction.addressing_value {
+        let value = instruction.addressing_value.get_value(registers, bus).unwrap();
+        if !bit_branch_condition(instruction.opcode, value).unwrap() {
+            return 0;
+        }
+        return 1 + get_address_from_relative(offset, registers).1 as Byte;
+    }
+    if let Some(taken) = branch_condition(instruction.opcode, &registers.status) {
+        if !taken {
+            return 0;
+        }
+        let target = instruction.addressing_value.get_effective_address(registers, bus).unwrap();
+        return 1 + target.page_crossed as Byte;
+    }
+    let decimal = variant.is_cmos()
+        && registers.status.get_d()
+        && matches!(instruction.opcode, Opcode::ADC | Opcode::SBC);
+    if !has_page_crossing_penalty(variant, instruction.opcode) {
+        return decimal as Byte;
+    }
+    let page_crossed = instruction
+        .addressing_value
+        .get_effective_address(registers, bus)
+        .is_some_and(|effective_address| effective_address.page_crossed);
+    decimal as Byte + page_crossed as Byte
+}
@@ -0,0 +1,66 @@
+use lazy_static::lazy_static;
+
+use crate::cpu::CpuVariant;
+use crate::instructions::cycles::{base_cycles, base_cycles_65c02};
+use crate::instructions::opcode::{handler, Handler};
+use crate::instructions::parser::{decode_opcode, decode_opcode_65c02};
+use crate::{AddressingModeType, Byte, Opcode};
+
+/// Everything needed to execute an opcode byte, decoded ahead of time
+#[derive(Clone, Copy)]
+pub struct DispatchEntry {
+    pub opcode: Opcode,
+    pub addressing_mode: AddressingModeType,
+    /// Length of the instruction in bytes, including the opcode
+    pub length: Byte,
+    /// Cycles taken without page crossing, branch or decimal mode penalties
+    pub cycles: Byte,
+    /// Handler used while the decimal flag is clear
+    pub handler: Handler,
+    /// Handler used while the decimal flag is set, only different on variants with decimal mode
+    pub decimal_handler: Handler,
+}
+
+/// Opcode bytes of one CPU variant, `None` where a byte does not decode
+pub type DispatchTable = [Option<DispatchEntry>; 256];
+
+// The decoders are not const, so the tables are built from them on first use instead
+lazy_static! {
+    static ref RICOH_2A03: DispatchTable = build_table(CpuVariant::Ricoh2A03);
+    static ref NMOS_6502: DispatchTable = build_table(CpuVariant::Nmos6502);
+    static ref WDC_65C02: DispatchTable = build_table(CpuVariant::Wdc65C02);
+}
+
+/// Dispatch table of the given CPU variant
+pub fn dispatch_table(variant: CpuVariant) -> &'static DispatchTable {
+    match variant {
+        CpuVariant::Ricoh2A03 => &RICOH_2A03,
+        CpuVariant::Nmos6502 => &NMOS_6502,
+        CpuVariant::Wdc65C02 => &WDC_65C02,
+    }
+}
+
+fn build_table(variant: CpuVariant) -> DispatchTable {
+    std::array::from_fn(|byte| build_entry(variant, byte as Byte))
+}
+
+fn build_entry(variant: CpuVariant, byte: Byte) -> Option<DispatchEntry> {
+    let (opcode, addressing_mode, cycles) = match variant {
+        CpuVariant::Wdc65C02 => {
+            let (opcode, addressing_mode) = decode_opcode_65c02(byte, 0).ok()?;
+            (opcode, addressing_mode, base_cycles_65c02(byte, opcode, addressing_mode))
+        }
+        CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => {
+            let (opcode, addressing_mode) = decode_opcode(byte, 0).ok()?;
+            (opcode, addressing_mode, base_cycles(opcode, addressing_mode))
+        }
+    };
+    Some(DispatchEntry {
+        opcode,
+        addressing_mode,
+        length: 1 + addressing_mode.operand_length(),
+        cycles,
+        handler: handler(opcode, variant, false),
+        decimal_handler: handler(opcode, variant, true),
+    })
+}
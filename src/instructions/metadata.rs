@@ -0,0 +1,313 @@
+use std::fmt;
+use std::ops::BitOr;
+
+use lazy_static::lazy_static;
+
+use crate::cpu::CpuVariant;
+use crate::instructions::dispatch::{dispatch_table, DispatchEntry};
+use crate::instructions::parser::is_undocumented_opcode;
+use crate::instructions::schedule::{access_kind, AccessKind};
+use crate::{AddressingModeType, Byte, Opcode};
+
+/// Set of status flags, as a mask in the layout of the status register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Flags(Byte);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    pub const C: Flags = Flags(0b0000_0001);
+    pub const Z: Flags = Flags(0b0000_0010);
+    pub const I: Flags = Flags(0b0000_0100);
+    pub const D: Flags = Flags(0b0000_1000);
+    pub const V: Flags = Flags(0b0100_0000);
+    pub const N: Flags = Flags(0b1000_0000);
+    /// Every flag that exists in the status register, B and bit 5 are only seen on the stack
+    pub const ALL: Flags = Flags(0b1100_1111);
+
+    pub const fn bits(&self) -> Byte {
+        self.0
+    }
+
+    pub const fn union(self, other: Flags) -> Flags {
+        Flags(self.0 | other.0)
+    }
+
+    pub const fn contains(&self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, other: Flags) -> Flags {
+        self.union(other)
+    }
+}
+
+/// Lists the flags in NV-BDIZC order, e.g. "NZC"
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (Flags::N, 'N'),
+            (Flags::V, 'V'),
+            (Flags::D, 'D'),
+            (Flags::I, 'I'),
+            (Flags::Z, 'Z'),
+            (Flags::C, 'C'),
+        ];
+        for (flag, name) in names {
+            if self.contains(flag) {
+                write!(f, "{name}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+const NZ: Flags = Flags::N.union(Flags::Z);
+const NZC: Flags = NZ.union(Flags::C);
+const NVZC: Flags = NZC.union(Flags::V);
+
+/// What an instruction does with memory and the program counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstructionKind {
+    /// Reads its operand, or pulls from the stack
+    Read,
+    /// Writes its operand, or pushes to the stack
+    Write,
+    /// Reads its operand and writes the result back
+    ReadModifyWrite,
+    /// Changes the program counter other than stepping over the instruction, or stops the CPU
+    ControlFlow,
+    /// Only works on registers
+    Internal,
+}
+
+/// Facts about an opcode byte on a particular CPU variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionInfo {
+    pub opcode: Opcode,
+    pub mnemonic: &'static str,
+    pub addressing_mode: AddressingModeType,
+    /// Operand bytes following the opcode
+    pub operand_bytes: Byte,
+    /// Cycles taken without page crossing, branch or decimal mode penalties
+    pub cycles: Byte,
+    /// Flags that influence the result
+    pub flags_read: Flags,
+    /// Flags the instruction may change
+    pub flags_written: Flags,
+    pub kind: InstructionKind,
+    /// Whether the opcode is one of the undocumented NMOS opcodes
+    pub undocumented: bool,
+}
+
+/// Opcode bytes of one CPU variant, `None` where a byte does not decode
+pub type InstructionTable = [Option<InstructionInfo>; 256];
+
+lazy_static! {
+    static ref RICOH_2A03: InstructionTable = build_table(CpuVariant::Ricoh2A03);
+    static ref NMOS_6502: InstructionTable = build_table(CpuVariant::Nmos6502);
+    static ref WDC_65C02: InstructionTable = build_table(CpuVariant::Wdc65C02);
+}
+
+/// Instruction metadata of the given CPU variant, indexed by opcode byte
+pub fn instruction_table(variant: CpuVariant) -> &'static InstructionTable {
+    match variant {
+        CpuVariant::Ricoh2A03 => &RICOH_2A03,
+        CpuVariant::Nmos6502 => &NMOS_6502,
+        CpuVariant::Wdc65C02 => &WDC_65C02,
+    }
+}
+
+/// Metadata of an opcode byte, `None` if it does not decode on the variant
+pub fn instruction_info(variant: CpuVariant, opcode: Byte) -> Option<&'static InstructionInfo> {
+    instruction_table(variant)[opcode as usize].as_ref()
+}
+
+fn build_table(variant: CpuVariant) -> InstructionTable {
+    let dispatch = dispatch_table(variant);
+    std::array::from_fn(|byte| {
+        let undocumented = !variant.is_cmos() && is_undocumented_opcode(byte as Byte);
+        dispatch[byte].as_ref().map(|entry| build_info(variant, entry, undocumented))
+    })
+}
+
+fn build_info(variant: CpuVariant, entry: &DispatchEntry, undocumented: bool) -> InstructionInfo {
+    InstructionInfo {
+        opcode: entry.opcode,
+        mnemonic: entry.opcode.mnemonic(),
+        addressing_mode: entry.addressing_mode,
+        operand_bytes: entry.addressing_mode.operand_length(),
+        cycles: entry.cycles,
+        flags_read: flags_read(entry.opcode, variant),
+        flags_written: flags_written(entry.opcode, entry.addressing_mode, variant),
+        kind: instruction_kind(entry.opcode, entry.addressing_mode),
+        undocumented,
+    }
+}
+
+fn flags_read(opcode: Opcode, variant: CpuVariant) -> Flags {
+    let decimal = match variant.has_decimal_mode() {
+        true => Flags::D,
+        false => Flags::NONE,
+    };
+    match opcode {
+        Opcode::ADC | Opcode::SBC | Opcode::RRA | Opcode::ISC => Flags::C | decimal,
+        Opcode::ROL | Opcode::ROR | Opcode::RLA | Opcode::ARR => Flags::C,
+        Opcode::BPL | Opcode::BMI => Flags::N,
+        Opcode::BVC | Opcode::BVS => Flags::V,
+        Opcode::BCC | Opcode::BCS => Flags::C,
+        Opcode::BNE | Opcode::BEQ => Flags::Z,
+        Opcode::PHP | Opcode::BRK => Flags::ALL,
+        _ => Flags::NONE,
+    }
+}
+
+fn flags_written(opcode: Opcode, addressing_mode: AddressingModeType, variant: CpuVariant) -> Flags {
+    match opcode {
+        Opcode::ADC | Opcode::SBC | Opcode::RRA | Opcode::ISC | Opcode::ARR => NVZC,
+        Opcode::ASL
+        | Opcode::LSR
+        | Opcode::ROL
+        | Opcode::ROR
+        | Opcode::SLO
+        | Opcode::RLA
+        | Opcode::SRE
+        | Opcode::CMP
+        | Opcode::CPX
+        | Opcode::CPY
+        | Opcode::DCP
+        | Opcode::ANC
+        | Opcode::ALR
+        | Opcode::AXS => NZC,
+        Opcode::AND
+        | Opcode::ORA
+        | Opcode::EOR
+        | Opcode::LDA
+        | Opcode::LDX
+        | Opcode::LDY
+        | Opcode::LAX
+        | Opcode::LAS
+        | Opcode::LXA
+        | Opcode::ANE
+        | Opcode::TAX
+        | Opcode::TAY
+        | Opcode::TSX
+        | Opcode::TXA
+        | Opcode::TYA
+        | Opcode::INC
+        | Opcode::INX
+        | Opcode::INY
+        | Opcode::DEC
+        | Opcode::DEX
+        | Opcode::DEY
+        | Opcode::PLA
+        | Opcode::PLX
+        | Opcode::PLY => NZ,
+        Opcode::BIT if addressing_mode == AddressingModeType::Immediate => Flags::Z,
+        Opcode::BIT => NZ | Flags::V,
+        Opcode::TRB | Opcode::TSB => Flags::Z,
+        Opcode::CLC | Opcode::SEC => Flags::C,
+        Opcode::CLD | Opcode::SED => Flags::D,
+        Opcode::CLI | Opcode::SEI => Flags::I,
+        Opcode::CLV => Flags::V,
+        Opcode::PLP | Opcode::RTI => Flags::ALL,
+        Opcode::BRK if variant.is_cmos() => Flags::I | Flags::D,
+        Opcode::BRK => Flags::I,
+        _ => Flags::NONE,
+    }
+}
+
+fn instruction_kind(opcode: Opcode, addressing_mode: AddressingModeType) -> InstructionKind {
+    match opcode {
+        Opcode::BPL
+        | Opcode::BMI
+        | Opcode::BVC
+        | Opcode::BVS
+        | Opcode::BCC
+        | Opcode::BCS
+        | Opcode::BNE
+        | Opcode::BEQ
+        | Opcode::BRA
+        | Opcode::BBR(_)
+        | Opcode::BBS(_)
+        | Opcode::JMP
+        | Opcode::JMP_ABS
+        | Opcode::JSR
+        | Opcode::RTS
+        | Opcode::RTI
+        | Opcode::BRK
+        | Opcode::JAM
+        | Opcode::STP
+        | Opcode::WAI => InstructionKind::ControlFlow,
+        Opcode::PHA | Opcode::PHP | Opcode::PHX | Opcode::PHY => InstructionKind::Write,
+        Opcode::PLA | Opcode::PLP | Opcode::PLX | Opcode::PLY => InstructionKind::Read,
+        _ => match addressing_mode {
+            AddressingModeType::Accumulator | AddressingModeType::Implied => InstructionKind::Internal,
+            _ => match access_kind(opcode) {
+                AccessKind::Read => InstructionKind::Read,
+                AccessKind::Write => InstructionKind::Write,
+                AccessKind::ReadModifyWrite => InstructionKind::ReadModifyWrite,
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(variant: CpuVariant, opcode: Byte) -> &'static InstructionInfo {
+        instruction_info(variant, opcode).unwrap()
+    }
+
+    #[test]
+    fn describes_official_opcodes() {
+        let adc = info(CpuVariant::Nmos6502, 0x69);
+        assert_eq!((adc.mnemonic, adc.operand_bytes, adc.cycles), ("ADC", 1, 2));
+        assert_eq!((adc.flags_read, adc.flags_written), (Flags::C | Flags::D, NVZC));
+        assert_eq!((adc.kind, adc.undocumented), (InstructionKind::Read, false));
+        // The 2A03 has no decimal mode for ADC to depend on
+        assert_eq!(info(CpuVariant::Ricoh2A03, 0x69).flags_read, Flags::C);
+        let inc = info(CpuVariant::Nmos6502, 0xfe);
+        assert_eq!((inc.mnemonic, inc.addressing_mode), ("INC", AddressingModeType::AbsoluteIndexedWithX));
+        assert_eq!((inc.operand_bytes, inc.cycles, inc.flags_written), (2, 7, NZ));
+        assert_eq!(inc.kind, InstructionKind::ReadModifyWrite);
+        assert_eq!(info(CpuVariant::Nmos6502, 0x28).flags_written, Flags::ALL);
+        assert_eq!(info(CpuVariant::Nmos6502, 0x20).kind, InstructionKind::ControlFlow);
+    }
+
+    #[test]
+    fn describes_undocumented_opcodes() {
+        let lax = info(CpuVariant::Nmos6502, 0xa7);
+        assert_eq!((lax.mnemonic, lax.operand_bytes, lax.cycles, lax.flags_written), ("LAX", 1, 3, NZ));
+        assert!(lax.undocumented);
+        let arr = info(CpuVariant::Ricoh2A03, 0x6b);
+        assert_eq!((arr.mnemonic, arr.flags_read, arr.flags_written), ("ARR", Flags::C, NVZC));
+        assert!(info(CpuVariant::Nmos6502, 0x1a).undocumented);
+        assert!(info(CpuVariant::Nmos6502, 0xeb).undocumented);
+        assert!(!info(CpuVariant::Nmos6502, 0xea).undocumented);
+    }
+
+    #[test]
+    fn describes_65c02_opcodes() {
+        let bit = info(CpuVariant::Wdc65C02, 0x89);
+        assert_eq!((bit.mnemonic, bit.addressing_mode), ("BIT", AddressingModeType::Immediate));
+        assert_eq!((bit.operand_bytes, bit.cycles, bit.flags_written), (1, 2, Flags::Z));
+        assert_eq!(info(CpuVariant::Wdc65C02, 0x2c).flags_written, NZ | Flags::V);
+        let stz = info(CpuVariant::Wdc65C02, 0x9c);
+        assert_eq!((stz.mnemonic, stz.operand_bytes, stz.cycles, stz.kind), ("STZ", 2, 4, InstructionKind::Write));
+        assert!(!stz.undocumented);
+        // BRK also clears D on the 65C02
+        assert_eq!(info(CpuVariant::Wdc65C02, 0x00).flags_written, Flags::I | Flags::D);
+        assert_eq!(info(CpuVariant::Nmos6502, 0x00).flags_written, Flags::I);
+        let nop = info(CpuVariant::Wdc65C02, 0x03);
+        assert_eq!((nop.mnemonic, nop.operand_bytes, nop.cycles), ("NOP", 0, 1));
+    }
+}
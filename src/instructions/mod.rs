@@ -1,2 +1,7 @@
+pub mod cycles;
+pub mod dispatch;
+pub mod metadata;
 pub mod opcode;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod schedule;
+pub mod stack;
\ No newline at end of file
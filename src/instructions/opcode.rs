@@ -1,196 +1,741 @@
-use crate::{AddressingModeType, Byte, Registers};
-
-pub struct Memory(pub Vec<Byte>);
-
-
-fn asl(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+use crate::bus::Bus;
+use crate::cpu::CpuVariant;
+use crate::instructions::parser::group_four::{
+    alr, anc, ane, arr, axs, dcp, isc, isc_decimal, las, lax, lxa, rla, rra, rra_decimal, sax, sha,
+    slo, sre, tas,
+};
+use crate::instructions::parser::group_one::{
+    adc, adc_decimal, adc_decimal_65c02, and, cmp, eor, lda, ora, sbc, sbc_decimal,
+    sbc_decimal_65c02, sta,
+};
+use crate::instructions::stack::{pull, pull_address, push, push_address};
+use crate::{
+    get_address_from_relative, get_value_from_absolute, set_value_to_absolute, AddressingValue,
+    Byte, Byte2, Instruction, Opcode, PushSource, Registers, StatusFlags,
+};
+
+/// Executes an instruction on its operand
+pub type Handler = fn(&AddressingValue, &mut Registers, &mut dyn Bus);
+
+pub const NMI_VECTOR: Byte2 = 0xfffa;
+pub const RESET_VECTOR: Byte2 = 0xfffc;
+pub const IRQ_VECTOR: Byte2 = 0xfffe;
+
+/// Handlers of the 65C02 bit instructions, indexed by the bit they operate on
+const RMB_HANDLERS: [Handler; 8] = [rmb::<0>, rmb::<1>, rmb::<2>, rmb::<3>, rmb::<4>, rmb::<5>, rmb::<6>, rmb::<7>];
+const SMB_HANDLERS: [Handler; 8] = [smb::<0>, smb::<1>, smb::<2>, smb::<3>, smb::<4>, smb::<5>, smb::<6>, smb::<7>];
+const BBR_HANDLERS: [Handler; 8] = [bbr::<0>, bbr::<1>, bbr::<2>, bbr::<3>, bbr::<4>, bbr::<5>, bbr::<6>, bbr::<7>];
+const BBS_HANDLERS: [Handler; 8] = [bbs::<0>, bbs::<1>, bbs::<2>, bbs::<3>, bbs::<4>, bbs::<5>, bbs::<6>, bbs::<7>];
+
+/// Executes a decoded instruction whose bytes have already been stepped over
+pub fn execute(instruction: &Instruction, registers: &mut Registers, bus: &mut dyn Bus) {
+    let handler = match registers.status.get_d() {
+        true => instruction.decimal_handler,
+        false => instruction.handler,
+    };
+    handler(&instruction.addressing_value, registers, bus);
+}
+
+/// Handler executing the instruction on the given variant while the decimal flag is in the given
+/// state
+pub fn handler(opcode: Opcode, variant: CpuVariant, decimal_flag: bool) -> Handler {
+    let decimal = variant.has_decimal_mode() && decimal_flag;
+    match opcode {
+        Opcode::ADC if decimal && variant.is_cmos() => adc_decimal_65c02,
+        Opcode::ADC if decimal => adc_decimal,
+        Opcode::SBC if decimal && variant.is_cmos() => sbc_decimal_65c02,
+        Opcode::SBC if decimal => sbc_decimal,
+        Opcode::RRA if decimal => rra_decimal,
+        Opcode::ISC if decimal => isc_decimal,
+        Opcode::JMP_ABS if variant.is_cmos() => jmp_indirect_65c02,
+        Opcode::ADC => adc,
+        Opcode::AND => and,
+        Opcode::ASL => asl,
+        Opcode::CMP => cmp,
+        Opcode::DEC => dec,
+        Opcode::EOR => eor,
+        Opcode::INC => inc,
+        Opcode::LDA => lda,
+        Opcode::LDX => ldx,
+        Opcode::LSR => lsr,
+        Opcode::ORA => ora,
+        Opcode::ROL => rol,
+        Opcode::ROR => ror,
+        Opcode::SBC => sbc,
+        Opcode::STA => sta,
+        Opcode::STX => stx,
+        Opcode::BIT => bit,
+        Opcode::CPX => cpx,
+        Opcode::CPY => cpy,
+        Opcode::JMP | Opcode::JMP_ABS => jmp,
+        Opcode::LDY => ldy,
+        Opcode::STY => sty,
+        Opcode::BPL => bpl,
+        Opcode::BCC => bcc,
+        Opcode::BCS => bcs,
+        Opcode::BEQ => beq,
+        Opcode::BMI => bmi,
+        Opcode::BNE => bne,
+        Opcode::BVC => bvc,
+        Opcode::BVS => bvs,
+        Opcode::BRK => brk,
+        Opcode::CLC => clc,
+        Opcode::CLD => cld,
+        Opcode::CLI => cli,
+        Opcode::CLV => clv,
+        Opcode::DEX => dex,
+        Opcode::DEY => dey,
+        Opcode::INX => inx,
+        Opcode::INY => iny,
+        Opcode::JSR => jsr,
+        Opcode::NOP => nop,
+        Opcode::PHA => pha,
+        Opcode::PHP => php,
+        Opcode::PLA => pla,
+        Opcode::PLP => plp,
+        Opcode::RTI => rti,
+        Opcode::RTS => rts,
+        Opcode::SEC => sec,
+        Opcode::SED => sed,
+        Opcode::SEI => sei,
+        Opcode::TAX => tax,
+        Opcode::TAY => tay,
+        Opcode::TSX => tsx,
+        Opcode::TXA => txa,
+        Opcode::TXS => txs,
+        Opcode::TYA => tya,
+        Opcode::SLO => slo,
+        Opcode::RLA => rla,
+        Opcode::SRE => sre,
+        Opcode::RRA => rra,
+        Opcode::SAX => sax,
+        Opcode::LAX => lax,
+        Opcode::DCP => dcp,
+        Opcode::ISC => isc,
+        Opcode::ANC => anc,
+        Opcode::ALR => alr,
+        Opcode::ARR => arr,
+        Opcode::ANE => ane,
+        Opcode::LXA => lxa,
+        Opcode::AXS => axs,
+        Opcode::SHA => sha,
+        Opcode::SHX => shx,
+        Opcode::SHY => shy,
+        Opcode::TAS => tas,
+        Opcode::LAS => las,
+        Opcode::JAM => jam,
+        Opcode::BRA => bra,
+        Opcode::PHX => phx,
+        Opcode::PHY => phy,
+        Opcode::PLX => plx,
+        Opcode::PLY => ply,
+        Opcode::STZ => stz,
+        Opcode::TRB => trb,
+        Opcode::TSB => tsb,
+        Opcode::RMB(bit) => RMB_HANDLERS[bit as usize],
+        Opcode::SMB(bit) => SMB_HANDLERS[bit as usize],
+        Opcode::BBR(bit) => BBR_HANDLERS[bit as usize],
+        Opcode::BBS(bit) => BBS_HANDLERS[bit as usize],
+        Opcode::WAI => nop,
+        Opcode::STP => jam,
+    }
+}
+
+/// Sets the zero and negative flags from a result
+pub fn update_zero_and_negative(value: Byte, registers: &mut Registers) {
+    registers.status.replace_z(value == 0);
+    registers.status.replace_n(value & 0x80 != 0);
+}
+
+/// Adds a value and the carry to the accumulator, setting C, V, Z and N
+pub fn add_with_carry(value: Byte, registers: &mut Registers) {
+    let sum = registers.a as Byte2 + value as Byte2 + registers.status.get_c() as Byte2;
+    let result = sum as Byte;
+    let overflow = (registers.a ^ result) & (value ^ result) & 0x80 != 0;
+    registers.status.replace_c(sum > 0xff);
+    registers.status.replace_v(overflow);
+    registers.a = result;
+    update_zero_and_negative(result, registers);
+}
+
+/// Adds a value and the carry to the accumulator as two BCD digits
+///
+/// The NMOS 6502 sets N and V from the result before the high digit is adjusted and Z from the
+/// binary sum, the 65C02 sets N and Z from the adjusted result.
+pub fn add_with_carry_decimal(value: Byte, cmos: bool, registers: &mut Registers) {
+    let a = registers.a;
+    let carry = registers.status.get_c() as Byte2;
+    let mut low = (a & 0x0f) as Byte2 + (value & 0x0f) as Byte2 + carry;
+    if low >= 0x0a {
+        low = ((low + 0x06) & 0x0f) + 0x10;
+    }
+    let mut sum = (a & 0xf0) as Byte2 + (value & 0xf0) as Byte2 + low;
+    let intermediate = sum as Byte;
+    if sum >= 0xa0 {
+        sum += 0x60;
+    }
+    registers.status.replace_c(sum > 0xff);
+    registers.status.replace_v((a ^ intermediate) & (value ^ intermediate) & 0x80 != 0);
+    registers.a = sum as Byte;
+    if cmos {
+        update_zero_and_negative(registers.a, registers);
+    } else {
+        registers.status.replace_n(intermediate & 0x80 != 0);
+        registers.status.replace_z(a.wrapping_add(value).wrapping_add(carry as Byte) == 0);
+    }
+}
+
+/// Subtracts a value and the borrow from the accumulator as two BCD digits
+///
+/// The flags are those of the binary subtraction, except N and Z on the 65C02 which come from
+/// the adjusted result.
+pub fn subtract_with_borrow_decimal(value: Byte, cmos: bool, registers: &mut Registers) {
+    let a = registers.a;
+    let borrow = !registers.status.get_c() as i16;
+    add_with_carry(!value, registers);
+    let low = (a & 0x0f) as i16 - (value & 0x0f) as i16 - borrow;
+    let result = if cmos {
+        let mut result = a as i16 - value as i16 - borrow;
+        if result < 0 {
+            result -= 0x60;
+        }
+        if low < 0 {
+            result -= 0x06;
+        }
+        result
+    } else {
+        let low = if low < 0 { ((low - 0x06) & 0x0f) - 0x10 } else { low };
+        let result = (a & 0xf0) as i16 - (value & 0xf0) as i16 + low;
+        if result < 0 { result - 0x60 } else { result }
+    };
+    registers.a = result as Byte;
+    if cmos {
+        update_zero_and_negative(registers.a, registers);
+    }
+}
+
+/// Compares a register against a value, setting C, Z and N
+pub fn compare(register: Byte, value: Byte, registers: &mut Registers) {
+    registers.status.replace_c(register >= value);
+    update_zero_and_negative(register.wrapping_sub(value), registers);
+}
+
+/// Writes a value to the accumulator or to the addressed memory location
+pub fn store(addressing_value: &AddressingValue, value: Byte, registers: &mut Registers, bus: &mut dyn Bus) {
+    match addressing_value {
+        AddressingValue::Accumulator => registers.a = value,
+        _ => {
+            let address = addressing_value.get_effective_address(registers, bus).unwrap().address;
+            set_value_to_absolute(address, value, bus);
+        }
+    }
+}
+
+/// Reads, modifies and writes back the operand, returning the new value
+pub fn modify(
+    addressing_value: &AddressingValue,
+    registers: &mut Registers,
+    bus: &mut dyn Bus,
+    operation: impl FnOnce(Byte, &mut Registers) -> Byte,
+) -> Byte {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    let result = operation(value, registers);
+    store(addressing_value, result, registers, bus);
+    update_zero_and_negative(result, registers);
+    result
+}
+
+/// Stores a value ANDed with the high byte of the unindexed address plus one, as SHA, SHX, SHY
+/// and TAS do
+///
+/// When indexing crosses a page the stored value also replaces the high byte of the address.
+pub fn store_and_high_byte(
+    addressing_value: &AddressingValue,
+    value: Byte,
+    registers: &mut Registers,
+    bus: &mut dyn Bus,
+) {
+    let effective_address = addressing_value.get_effective_address(registers, bus).unwrap();
+    let address = effective_address.address;
+    let base_high = ((address >> 8) as Byte).wrapping_sub(effective_address.page_crossed as Byte);
+    let value = value & base_high.wrapping_add(1);
+    let address = match effective_address.page_crossed {
+        true => (value as Byte2) << 8 | (address & 0x00ff),
+        false => address,
+    };
+    set_value_to_absolute(address, value, bus);
+}
+
+/// Whether a conditional branch is taken with the given flags, `None` for other instructions
+pub fn branch_condition(opcode: Opcode, status: &StatusFlags) -> Option<bool> {
+    match opcode {
+        Opcode::BPL => Some(!status.get_n()),
+        Opcode::BMI => Some(status.get_n()),
+        Opcode::BVC => Some(!status.get_v()),
+        Opcode::BVS => Some(status.get_v()),
+        Opcode::BCC => Some(!status.get_c()),
+        Opcode::BCS => Some(status.get_c()),
+        Opcode::BNE => Some(!status.get_z()),
+        Opcode::BEQ => Some(status.get_z()),
+        Opcode::BRA => Some(true),
+        _ => None,
+    }
+}
+
+/// Whether a 65C02 bit branch is taken for the tested value, `None` for other instructions
+pub fn bit_branch_condition(opcode: Opcode, value: Byte) -> Option<bool> {
+    match opcode {
+        Opcode::BBR(bit) => Some(value & (1 << bit) == 0),
+        Opcode::BBS(bit) => Some(value & (1 << bit) != 0),
+        _ => None,
+    }
+}
+
+fn branch(opcode: Opcode, addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    if branch_condition(opcode, &registers.status).unwrap() {
+        registers.p = addressing_value.get_effective_address(registers, bus).unwrap().address;
+    }
+}
+
+/// Reads a 16-bit address from an interrupt vector
+pub fn read_vector(vector: Byte2, bus: &mut dyn Bus) -> Byte2 {
+    let low = get_value_from_absolute(vector, bus);
+    let high = get_value_from_absolute(vector.wrapping_add(1), bus);
+    ((high as Byte2) << 8) | low as Byte2
 }
 
-fn bcc(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Pushes the program counter and status, sets I and jumps through an interrupt vector
+pub fn interrupt(vector: Byte2, source: PushSource, registers: &mut Registers, bus: &mut dyn Bus) {
+    push_address(registers.p, registers, bus);
+    push(registers.status.to_pushed_byte(source), registers, bus);
+    registers.status.replace_i(true);
+    registers.p = read_vector(vector, bus);
 }
 
-fn bcs(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Arithmetic Shift Left
+fn asl(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    modify(addressing_value, registers, bus, |value, registers| {
+        registers.status.replace_c(value & 0x80 != 0);
+        value << 1
+    });
 }
 
-fn beq(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Carry Clear
+fn bcc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BCC, addressing_value, registers, bus);
 }
 
-fn bit(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Carry Set
+fn bcs(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BCS, addressing_value, registers, bus);
 }
 
-fn bmi(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Equal
+fn beq(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BEQ, addressing_value, registers, bus);
 }
 
-fn bne(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Bit Test
+fn bit(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    registers.status.replace_z(registers.a & value == 0);
+    // The 65C02 immediate form only has a value to test against, not N and V to copy
+    if let AddressingValue::Immediate(_) = addressing_value {
+        return;
+    }
+    registers.status.replace_v(value & 0x40 != 0);
+    registers.status.replace_n(value & 0x80 != 0);
 }
 
-fn bpl(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Minus
+fn bmi(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BMI, addressing_value, registers, bus);
 }
 
-fn brk(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Not Equal
+fn bne(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BNE, addressing_value, registers, bus);
 }
 
-fn bvc(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Positive
+fn bpl(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BPL, addressing_value, registers, bus);
 }
 
-fn bvs(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Force Interrupt
+fn brk(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    // BRK skips a padding byte after the opcode
+    registers.p = registers.p.wrapping_add(1);
+    interrupt(IRQ_VECTOR, PushSource::Brk, registers, bus);
 }
 
-fn clc(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Overflow Clear
+fn bvc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BVC, addressing_value, registers, bus);
 }
 
-fn cld(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Branch if Overflow Set
+fn bvs(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BVS, addressing_value, registers, bus);
 }
 
-fn cli(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Clear Carry Flag
+fn clc(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_c(false);
 }
 
-fn clv(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Clear Decimal Mode
+fn cld(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_d(false);
 }
 
-fn cpx(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Clear Interrupt Disable
+fn cli(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_i(false);
 }
 
-fn cpy(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Clear Overflow Flag
+fn clv(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_v(false);
 }
 
-fn dec(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Compare X Register
+fn cpx(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    compare(registers.x, value, registers);
 }
 
-fn dex(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Compare Y Register
+fn cpy(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    compare(registers.y, value, registers);
 }
 
-fn dey(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Decrement Memory
+fn dec(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    modify(addressing_value, registers, bus, |value, _| value.wrapping_sub(1));
 }
 
-fn inc(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Decrement X Register
+fn dex(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.x = registers.x.wrapping_sub(1);
+    update_zero_and_negative(registers.x, registers);
 }
 
-fn inx(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Decrement Y Register
+fn dey(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.y = registers.y.wrapping_sub(1);
+    update_zero_and_negative(registers.y, registers);
 }
 
-fn iny(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Increment Memory
+fn inc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    modify(addressing_value, registers, bus, |value, _| value.wrapping_add(1));
+}
 
+/// Increment X Register
+fn inx(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.x = registers.x.wrapping_add(1);
+    update_zero_and_negative(registers.x, registers);
 }
 
-fn jmp(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Increment Y Register
+fn iny(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.y = registers.y.wrapping_add(1);
+    update_zero_and_negative(registers.y, registers);
+}
 
+/// Jump
+fn jmp(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.p = addressing_value.get_effective_address(registers, bus).unwrap().address;
 }
 
-fn jsr(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Jump to Subroutine
+fn jsr(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let target = addressing_value.get_effective_address(registers, bus).unwrap().address;
+    // The pushed return address points at the last byte of the JSR instruction
+    push_address(registers.p.wrapping_sub(1), registers, bus);
+    registers.p = target;
+}
 
+/// Load X Register
+fn ldx(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.x = addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.x, registers);
 }
 
-fn ldx(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Load Y Register
+fn ldy(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.y = addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.y, registers);
+}
 
+/// Logical Shift Right
+fn lsr(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    modify(addressing_value, registers, bus, |value, registers| {
+        registers.status.replace_c(value & 0x01 != 0);
+        value >> 1
+    });
 }
 
-fn ldy(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// No Operation
+fn nop(_addressing_value: &AddressingValue, _registers: &mut Registers, _bus: &mut dyn Bus) {}
 
+/// Push Accumulator
+fn pha(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    push(registers.a, registers, bus);
 }
 
-fn lsr(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Push Processor Status
+fn php(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    push(registers.status.to_pushed_byte(PushSource::Php), registers, bus);
 }
 
-fn nop(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Pull Accumulator
+fn pla(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.a = pull(registers, bus);
+    update_zero_and_negative(registers.a, registers);
+}
 
+/// Pull Processor Status
+fn plp(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = pull(registers, bus);
+    registers.status.replace_from_pulled_byte(value);
 }
 
-fn pha(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Rotate Left
+fn rol(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    modify(addressing_value, registers, bus, |value, registers| {
+        let carry = registers.status.replace_c(value & 0x80 != 0);
+        value << 1 | carry as Byte
+    });
+}
 
+/// Rotate Right
+fn ror(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    modify(addressing_value, registers, bus, |value, registers| {
+        let carry = registers.status.replace_c(value & 0x01 != 0);
+        value >> 1 | (carry as Byte) << 7
+    });
 }
 
-fn php(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Return from Interrupt
+fn rti(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = pull(registers, bus);
+    registers.status.replace_from_pulled_byte(value);
+    registers.p = pull_address(registers, bus);
+}
 
+/// Return from Subroutine
+fn rts(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.p = pull_address(registers, bus).wrapping_add(1);
 }
 
-fn pla(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Set Carry Flag
+fn sec(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_c(true);
+}
 
+/// Set Decimal Flag
+fn sed(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_d(true);
 }
 
-fn plp(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Set Interrupt Disable
+fn sei(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.status.replace_i(true);
+}
 
+/// Store X Register
+fn stx(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store(addressing_value, registers.x, registers, bus);
 }
 
-fn rol(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Store Y Register
+fn sty(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store(addressing_value, registers.y, registers, bus);
+}
 
+/// Transfer Accumulator to X
+fn tax(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.x = registers.a;
+    update_zero_and_negative(registers.x, registers);
 }
 
-fn ror(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Transfer Accumulator to Y
+fn tay(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.y = registers.a;
+    update_zero_and_negative(registers.y, registers);
+}
 
+/// Transfer Stack Pointer to X
+fn tsx(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.x = registers.s;
+    update_zero_and_negative(registers.x, registers);
 }
 
-fn rti(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Transfer X to Accumulator
+fn txa(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.a = registers.x;
+    update_zero_and_negative(registers.a, registers);
+}
 
+/// Transfer X to Stack Pointer
+fn txs(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.s = registers.x;
 }
 
-fn rts(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Transfer Y to Accumulator
+fn tya(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.a = registers.y;
+    update_zero_and_negative(registers.a, registers);
+}
 
+/// Unstable: store X AND the high byte of the address plus one
+fn shx(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store_and_high_byte(addressing_value, registers.x, registers, bus);
 }
 
-fn sec(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Unstable: store Y AND the high byte of the address plus one
+fn shy(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store_and_high_byte(addressing_value, registers.y, registers, bus);
+}
 
+/// Halt the CPU, which keeps fetching the same opcode until it is reset
+fn jam(_addressing_value: &AddressingValue, registers: &mut Registers, _bus: &mut dyn Bus) {
+    registers.p = registers.p.wrapping_sub(1);
 }
 
-fn sed(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Branch Always
+fn bra(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    branch(Opcode::BRA, addressing_value, registers, bus);
+}
 
+/// Jump through a pointer, carrying into the next page for JMP ($xxFF) as the 65C02 does
+fn jmp_indirect_65c02(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.p = match addressing_value {
+        AddressingValue::AbsoluteIndirect(pointer) => read_vector(*pointer, bus),
+        _ => addressing_value.get_effective_address(registers, bus).unwrap().address,
+    };
 }
 
-fn sei(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Push X Register
+fn phx(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    push(registers.x, registers, bus);
+}
 
+/// Push Y Register
+fn phy(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    push(registers.y, registers, bus);
 }
 
-fn stx(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Pull X Register
+fn plx(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.x = pull(registers, bus);
+    update_zero_and_negative(registers.x, registers);
+}
 
+/// Pull Y Register
+fn ply(_addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.y = pull(registers, bus);
+    update_zero_and_negative(registers.y, registers);
 }
 
-fn sty(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Store Zero
+fn stz(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store(addressing_value, 0, registers, bus);
+}
 
+/// Test and Reset Bits
+fn trb(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    registers.status.replace_z(registers.a & value == 0);
+    store(addressing_value, value & !registers.a, registers, bus);
 }
 
-fn tax(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Test and Set Bits
+fn tsb(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    registers.status.replace_z(registers.a & value == 0);
+    store(addressing_value, value | registers.a, registers, bus);
+}
 
+/// Reset Memory Bit
+fn rmb<const BIT: Byte>(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    store(addressing_value, value & !(1 << BIT), registers, bus);
 }
 
-fn tay(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Set Memory Bit
+fn smb<const BIT: Byte>(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    store(addressing_value, value | 1 << BIT, registers, bus);
+}
 
+/// Branch on Bit Reset
+fn bbr<const BIT: Byte>(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    bit_branch(Opcode::BBR(BIT), addressing_value, registers, bus);
 }
 
-fn tsx(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Branch on Bit Set
+fn bbs<const BIT: Byte>(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    bit_branch(Opcode::BBS(BIT), addressing_value, registers, bus);
+}
 
+fn bit_branch(opcode: Opcode, addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    if let AddressingValue::ZeroPageRelative(_, offset) = addressing_value {
+        if bit_branch_condition(opcode, value).unwrap() {
+            registers.p = get_address_from_relative(*offset, registers).0;
+        }
+    }
 }
 
-fn txa(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Memory;
 
-}
+    /// Runs ADC or SBC with the decimal flag set, returning A and the flags as NV-BDIZC
+    fn run(opcode: Opcode, variant: CpuVariant, a: Byte, value: Byte, carry: bool) -> (Byte, String) {
+        let mut registers = Registers::new(0);
+        registers.a = a;
+        registers.status.replace_d(true);
+        registers.status.replace_c(carry);
+        let handler = handler(opcode, variant, true);
+        handler(&AddressingValue::Immediate(value), &mut registers, &mut Memory::new());
+        (registers.a, registers.status.to_string())
+    }
 
-fn txs(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+    #[test]
+    fn adc_decimal_takes_n_and_z_from_the_binary_sum_on_nmos() {
+        assert_eq!(run(Opcode::ADC, CpuVariant::Nmos6502, 0x99, 0x01, false), (0x00, "Nv-bDizC".into()));
+        assert_eq!(run(Opcode::ADC, CpuVariant::Nmos6502, 0x79, 0x00, true), (0x80, "NV-bDizc".into()));
+    }
 
-}
+    #[test]
+    fn adc_decimal_takes_n_and_z_from_the_result_on_65c02() {
+        assert_eq!(run(Opcode::ADC, CpuVariant::Wdc65C02, 0x99, 0x01, false), (0x00, "nv-bDiZC".into()));
+        assert_eq!(run(Opcode::ADC, CpuVariant::Wdc65C02, 0x79, 0x00, true), (0x80, "NV-bDizc".into()));
+    }
+
+    #[test]
+    fn sbc_decimal_borrows_across_both_digits() {
+        assert_eq!(run(Opcode::SBC, CpuVariant::Nmos6502, 0x00, 0x01, true), (0x99, "Nv-bDizc".into()));
+        assert_eq!(run(Opcode::SBC, CpuVariant::Wdc65C02, 0x00, 0x01, true), (0x99, "Nv-bDizc".into()));
+        assert_eq!(run(Opcode::SBC, CpuVariant::Nmos6502, 0x50, 0x25, true), (0x25, "nv-bDizC".into()));
+    }
 
-fn tya(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+    #[test]
+    fn sbc_decimal_takes_z_from_the_result_on_65c02() {
+        // $00 - $A0 adjusts to $00 while the binary difference is $60
+        assert_eq!(run(Opcode::SBC, CpuVariant::Nmos6502, 0x00, 0xa0, true), (0x00, "nv-bDizc".into()));
+        assert_eq!(run(Opcode::SBC, CpuVariant::Wdc65C02, 0x00, 0xa0, true), (0x00, "nv-bDiZc".into()));
+    }
 
+    #[test]
+    fn ricoh_2a03_ignores_the_decimal_flag() {
+        assert_eq!(run(Opcode::ADC, CpuVariant::Ricoh2A03, 0x99, 0x01, false), (0x9a, "Nv-bDizc".into()));
+        assert_eq!(run(Opcode::SBC, CpuVariant::Ricoh2A03, 0x00, 0x01, true), (0xff, "Nv-bDizc".into()));
+    }
 }
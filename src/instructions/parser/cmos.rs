@@ -0,0 +1,61 @@
+use crate::instructions::parser::group_one::decode_opcode_aaa_xxx_01;
+use crate::instructions::parser::{decode_opcode, is_undocumented_opcode, DecodeError};
+use crate::{AddressingModeType, Byte, Byte2, Opcode};
+
+/// Decodes an opcode of the WDC 65C02
+///
+/// The documented NMOS opcodes keep their meaning, the remaining slots hold the CMOS additions
+/// or NOPs of various lengths, so every byte decodes.
+pub fn decode_opcode_65c02(opcode: Byte, address: Byte2) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    let bit = (opcode >> 4) & 0b111;
+    match opcode {
+        0x80 => Ok((Opcode::BRA, AddressingModeType::Relative)),
+        0xDA => Ok((Opcode::PHX, AddressingModeType::Implied)),
+        0x5A => Ok((Opcode::PHY, AddressingModeType::Implied)),
+        0xFA => Ok((Opcode::PLX, AddressingModeType::Implied)),
+        0x7A => Ok((Opcode::PLY, AddressingModeType::Implied)),
+        0x64 => Ok((Opcode::STZ, AddressingModeType::ZeroPage)),
+        0x74 => Ok((Opcode::STZ, AddressingModeType::ZeroPageIndexedWithX)),
+        0x9C => Ok((Opcode::STZ, AddressingModeType::Absolute)),
+        0x9E => Ok((Opcode::STZ, AddressingModeType::AbsoluteIndexedWithX)),
+        0x04 => Ok((Opcode::TSB, AddressingModeType::ZeroPage)),
+        0x0C => Ok((Opcode::TSB, AddressingModeType::Absolute)),
+        0x14 => Ok((Opcode::TRB, AddressingModeType::ZeroPage)),
+        0x1C => Ok((Opcode::TRB, AddressingModeType::Absolute)),
+        0x89 => Ok((Opcode::BIT, AddressingModeType::Immediate)),
+        0x34 => Ok((Opcode::BIT, AddressingModeType::ZeroPageIndexedWithX)),
+        0x3C => Ok((Opcode::BIT, AddressingModeType::AbsoluteIndexedWithX)),
+        0x1A => Ok((Opcode::INC, AddressingModeType::Accumulator)),
+        0x3A => Ok((Opcode::DEC, AddressingModeType::Accumulator)),
+        0x7C => Ok((Opcode::JMP_ABS, AddressingModeType::AbsoluteIndexedIndirect)),
+        0xCB => Ok((Opcode::WAI, AddressingModeType::Implied)),
+        0xDB => Ok((Opcode::STP, AddressingModeType::Implied)),
+        // The "Group One" instructions gain zero page indirect in the unused xxx_100_10 slots
+        op if op & 0b000_111_11 == 0b000_100_10 => Ok((
+            decode_opcode_aaa_xxx_01(op ^ 0b11, address)?,
+            AddressingModeType::ZeroPageIndirect,
+        )),
+        op if op & 0b0000_1111 == 0b0000_0111 => match op & 0x80 {
+            0 => Ok((Opcode::RMB(bit), AddressingModeType::ZeroPage)),
+            _ => Ok((Opcode::SMB(bit), AddressingModeType::ZeroPage)),
+        },
+        op if op & 0b0000_1111 == 0b0000_1111 => match op & 0x80 {
+            0 => Ok((Opcode::BBR(bit), AddressingModeType::ZeroPageRelative)),
+            _ => Ok((Opcode::BBS(bit), AddressingModeType::ZeroPageRelative)),
+        },
+        op if !is_undocumented_opcode(op) => decode_opcode(op, address),
+        op => Ok((Opcode::NOP, decode_nop_addressing_mode(op))),
+    }
+}
+
+/// Operand size of the 65C02 NOPs sitting in the undocumented NMOS slots
+fn decode_nop_addressing_mode(opcode: Byte) -> AddressingModeType {
+    match opcode {
+        op if op & 0b0000_1111 == 0b0000_0010 => AddressingModeType::Immediate,
+        0x44 => AddressingModeType::ZeroPage,
+        0x54 | 0xD4 | 0xF4 => AddressingModeType::ZeroPageIndexedWithX,
+        // Unlike their NMOS counterparts these do not index
+        0x5C | 0xDC | 0xFC => AddressingModeType::Absolute,
+        _ => AddressingModeType::Implied,
+    }
+}
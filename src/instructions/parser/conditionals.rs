@@ -1,17 +1,18 @@
-use crate::Byte;
+use crate::instructions::parser::DecodeError;
+use crate::{Byte, Byte2};
 use crate::Opcode;
 
 /// Conditional branch instructions
-pub fn decode_opcode_xx_y_10000(opcode: Byte) -> Opcode {
+pub fn decode_opcode_xx_y_10000(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
     match opcode & 0b111_1_0000 {
-        0b000_10000 => Opcode::BPL,
-        0b001_10000 => Opcode::BMI,
-        0b010_10000 => Opcode::BVC,
-        0b011_10000 => Opcode::BVS,
-        0b100_10000 => Opcode::BCC,
-        0b101_10000 => Opcode::BCS,
-        0b110_10000 => Opcode::BNE,
-        0b111_10000 => Opcode::BEQ,
-        op => unreachable!("{op}"),
+        0b000_10000 => Ok(Opcode::BPL),
+        0b001_10000 => Ok(Opcode::BMI),
+        0b010_10000 => Ok(Opcode::BVC),
+        0b011_10000 => Ok(Opcode::BVS),
+        0b100_10000 => Ok(Opcode::BCC),
+        0b101_10000 => Ok(Opcode::BCS),
+        0b110_10000 => Ok(Opcode::BNE),
+        0b111_10000 => Ok(Opcode::BEQ),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
 }
@@ -0,0 +1,300 @@
+use crate::bus::Bus;
+use crate::instructions::opcode::{
+    add_with_carry, add_with_carry_decimal, compare, modify, store, store_and_high_byte,
+    subtract_with_borrow_decimal, update_zero_and_negative,
+};
+use crate::instructions::parser::DecodeError;
+use crate::{AddressingModeType, AddressingValue, Byte, Byte2, Opcode, Registers};
+
+/// Value the unstable ANE and LXA instructions OR into the accumulator
+const UNSTABLE_MAGIC: Byte = 0xee;
+
+/// Undocumented "Group Four" instructions, each combining a group one and group two instruction
+pub fn decode_opcode_aaa_xxx_11(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
+    match opcode & 0b111_000_11 {
+        0b000_000_11 => Ok(Opcode::SLO),
+        0b001_000_11 => Ok(Opcode::RLA),
+        0b010_000_11 => Ok(Opcode::SRE),
+        0b011_000_11 => Ok(Opcode::RRA),
+        0b100_000_11 => Ok(Opcode::SAX),
+        0b101_000_11 => Ok(Opcode::LAX),
+        0b110_000_11 => Ok(Opcode::DCP),
+        0b111_000_11 => Ok(Opcode::ISC),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
+    }
+}
+
+pub fn decode_addressing_mode_xxx_bbb_11(opcode: Byte, address: Byte2) -> Result<AddressingModeType, DecodeError> {
+    match opcode & 0b000_111_11 {
+        0b000_000_11 => Ok(AddressingModeType::IndexedIndirect),
+        0b000_001_11 => Ok(AddressingModeType::ZeroPage),
+        0b000_010_11 => Ok(AddressingModeType::Immediate),
+        0b000_011_11 => Ok(AddressingModeType::Absolute),
+        0b000_100_11 => Ok(AddressingModeType::IndirectIndexed),
+        // With SAX and LAX this is ZeroPageIndexedWithY
+        0b000_101_11 => Ok(AddressingModeType::ZeroPageIndexedWithX),
+        0b000_110_11 => Ok(AddressingModeType::AbsoluteIndexedWithY),
+        // With SAX and LAX this is AbsoluteIndexedWithY
+        0b000_111_11 => Ok(AddressingModeType::AbsoluteIndexedWithX),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
+    }
+}
+
+/// Decodes a "Group Four" opcode into its instruction and addressing mode
+pub fn decode_instruction_aaa_bbb_11(
+    opcode: Byte,
+    address: Byte2,
+) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    // The immediate slots and the store-like slots next to SAX do their own thing
+    let special = match opcode {
+        0x0B | 0x2B => Some((Opcode::ANC, AddressingModeType::Immediate)),
+        0x4B => Some((Opcode::ALR, AddressingModeType::Immediate)),
+        0x6B => Some((Opcode::ARR, AddressingModeType::Immediate)),
+        0x8B => Some((Opcode::ANE, AddressingModeType::Immediate)),
+        0xAB => Some((Opcode::LXA, AddressingModeType::Immediate)),
+        0xCB => Some((Opcode::AXS, AddressingModeType::Immediate)),
+        0xEB => Some((Opcode::SBC, AddressingModeType::Immediate)),
+        0x93 => Some((Opcode::SHA, AddressingModeType::IndirectIndexed)),
+        0x9B => Some((Opcode::TAS, AddressingModeType::AbsoluteIndexedWithY)),
+        0x9F => Some((Opcode::SHA, AddressingModeType::AbsoluteIndexedWithY)),
+        0xBB => Some((Opcode::LAS, AddressingModeType::AbsoluteIndexedWithY)),
+        _ => None,
+    };
+    if let Some(decoded) = special {
+        return Ok(decoded);
+    }
+    match (
+        decode_opcode_aaa_xxx_11(opcode, address)?,
+        decode_addressing_mode_xxx_bbb_11(opcode, address)?,
+    ) {
+        (op @ (Opcode::SAX | Opcode::LAX), AddressingModeType::ZeroPageIndexedWithX) => {
+            Ok((op, AddressingModeType::ZeroPageIndexedWithY))
+        }
+        (op @ (Opcode::SAX | Opcode::LAX), AddressingModeType::AbsoluteIndexedWithX) => {
+            Ok((op, AddressingModeType::AbsoluteIndexedWithY))
+        }
+        decoded => Ok(decoded),
+    }
+}
+
+/// ASL memory then ORA
+pub fn slo(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = modify(addressing_value, registers, bus, |value, registers| {
+        registers.status.replace_c(value & 0x80 != 0);
+        value << 1
+    });
+    registers.a |= value;
+    update_zero_and_negative(registers.a, registers);
+}
+
+/// ROL memory then AND
+pub fn rla(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = modify(addressing_value, registers, bus, |value, registers| {
+        let carry = registers.status.replace_c(value & 0x80 != 0);
+        value << 1 | carry as Byte
+    });
+    registers.a &= value;
+    update_zero_and_negative(registers.a, registers);
+}
+
+/// LSR memory then EOR
+pub fn sre(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = modify(addressing_value, registers, bus, |value, registers| {
+        registers.status.replace_c(value & 0x01 != 0);
+        value >> 1
+    });
+    registers.a ^= value;
+    update_zero_and_negative(registers.a, registers);
+}
+
+/// ROR memory then ADC
+pub fn rra(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = rotate_right(addressing_value, registers, bus);
+    add_with_carry(value, registers);
+}
+
+/// Rotate right then add with carry in decimal mode
+pub fn rra_decimal(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = rotate_right(addressing_value, registers, bus);
+    add_with_carry_decimal(value, false, registers);
+}
+
+fn rotate_right(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) -> Byte {
+    modify(addressing_value, registers, bus, |value, registers| {
+        let carry = registers.status.replace_c(value & 0x01 != 0);
+        value >> 1 | (carry as Byte) << 7
+    })
+}
+
+/// Store A AND X
+pub fn sax(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store(addressing_value, registers.a & registers.x, registers, bus);
+}
+
+/// Load A and X
+pub fn lax(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    registers.a = value;
+    registers.x = value;
+    update_zero_and_negative(value, registers);
+}
+
+/// DEC memory then CMP
+pub fn dcp(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = modify(addressing_value, registers, bus, |value, _| value.wrapping_sub(1));
+    compare(registers.a, value, registers);
+}
+
+/// INC memory then SBC
+pub fn isc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = modify(addressing_value, registers, bus, |value, _| value.wrapping_add(1));
+    add_with_carry(!value, registers);
+}
+
+/// Increment then subtract with borrow in decimal mode
+pub fn isc_decimal(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = modify(addressing_value, registers, bus, |value, _| value.wrapping_add(1));
+    subtract_with_borrow_decimal(value, false, registers);
+}
+
+/// AND immediate, copying N into C
+pub fn anc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.a &= addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.a, registers);
+    registers.status.replace_c(registers.a & 0x80 != 0);
+}
+
+/// AND immediate then LSR A
+pub fn alr(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = registers.a & addressing_value.get_value(registers, bus).unwrap();
+    registers.status.replace_c(value & 0x01 != 0);
+    registers.a = value >> 1;
+    update_zero_and_negative(registers.a, registers);
+}
+
+/// AND immediate then ROR A, with C and V taken from bits 6 and 5 of the result
+pub fn arr(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = registers.a & addressing_value.get_value(registers, bus).unwrap();
+    registers.a = value >> 1 | (registers.status.get_c() as Byte) << 7;
+    update_zero_and_negative(registers.a, registers);
+    let bit_six = registers.a & 0x40 != 0;
+    let bit_five = registers.a & 0x20 != 0;
+    registers.status.replace_c(bit_six);
+    registers.status.replace_v(bit_six ^ bit_five);
+}
+
+/// Unstable: AND X and immediate into A
+pub fn ane(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    registers.a = (registers.a | UNSTABLE_MAGIC) & registers.x & value;
+    update_zero_and_negative(registers.a, registers);
+}
+
+/// Unstable: AND immediate into A and X
+pub fn lxa(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = (registers.a | UNSTABLE_MAGIC) & addressing_value.get_value(registers, bus).unwrap();
+    registers.a = value;
+    registers.x = value;
+    update_zero_and_negative(value, registers);
+}
+
+/// Subtract immediate from A AND X without borrow, also known as SBX
+pub fn axs(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    compare(registers.a & registers.x, value, registers);
+    registers.x = (registers.a & registers.x).wrapping_sub(value);
+}
+
+/// Unstable: store A AND X AND the high byte of the address plus one
+pub fn sha(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store_and_high_byte(addressing_value, registers.a & registers.x, registers, bus);
+}
+
+/// Unstable: transfer A AND X to S, then store it like SHA
+pub fn tas(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.s = registers.a & registers.x;
+    store_and_high_byte(addressing_value, registers.s, registers, bus);
+}
+
+/// Load memory AND S into A, X and S
+pub fn las(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap() & registers.s;
+    registers.a = value;
+    registers.x = value;
+    registers.s = value;
+    update_zero_and_negative(value, registers);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Memory;
+    use crate::cpu::Cpu;
+    use crate::StatusFlags;
+
+    use super::*;
+
+    /// One instruction at $0600 with $80 at $0010, and the state expected after it
+    struct Case {
+        program: &'static [Byte],
+        a: Byte,
+        x: Byte,
+        status: Byte,
+        expected_a: Byte,
+        expected_x: Byte,
+        expected_status: Byte,
+        cycles: Byte,
+    }
+
+    /// Two-cycle case from the A, X and status before and after
+    const fn case(program: &'static [Byte], before: (Byte, Byte, Byte), after: (Byte, Byte, Byte)) -> Case {
+        let ((a, x, status), (expected_a, expected_x, expected_status)) = (before, after);
+        Case { program, a, x, status, expected_a, expected_x, expected_status, cycles: 2 }
+    }
+
+    const fn nop(program: &'static [Byte], x: Byte, cycles: Byte) -> Case {
+        Case { cycles, ..case(program, (0x00, x, 0x24), (0x00, x, 0x24)) }
+    }
+
+    const CASES: [Case; 14] = [
+        // ARR takes C from bit 6 of the result and V from bit 6 XOR bit 5
+        case(&[0x6b, 0xff], (0xff, 0x00, 0x25), (0xff, 0x00, 0xa5)),
+        case(&[0x6b, 0xff], (0x80, 0x00, 0x24), (0x40, 0x00, 0x65)),
+        case(&[0x6b, 0xff], (0x40, 0x00, 0x24), (0x20, 0x00, 0x64)),
+        // AXS sets C like CMP on A AND X and ignores the incoming carry
+        case(&[0xcb, 0x10], (0xff, 0x30, 0x24), (0xff, 0x20, 0x25)),
+        case(&[0xcb, 0x40], (0xff, 0x30, 0x25), (0xff, 0xf0, 0xa4)),
+        case(&[0xcb, 0x30], (0xf0, 0x3f, 0x24), (0xf0, 0x00, 0x27)),
+        // LAX loads A and X together
+        Case { cycles: 3, ..case(&[0xa7, 0x10], (0x00, 0x00, 0x26), (0x80, 0x80, 0xa4)) },
+        // The NOPs differ only in length and cycles
+        nop(&[0x1a], 0x00, 2),
+        nop(&[0x80, 0x00], 0x00, 2),
+        nop(&[0x04, 0x10], 0x00, 3),
+        nop(&[0x14, 0x10], 0x00, 4),
+        nop(&[0x0c, 0x00, 0x02], 0x00, 4),
+        nop(&[0x1c, 0xff, 0x02], 0x00, 4),
+        nop(&[0x1c, 0xff, 0x02], 0x01, 5),
+    ];
+
+    #[test]
+    fn undocumented_opcodes_match_the_hardware() {
+        for case in CASES {
+            let mut memory = Memory::new();
+            memory.load(0x0010, &[0x80]);
+            memory.load(0x0600, case.program);
+            let mut cpu = Cpu::new(memory, 0x0600);
+            let registers = cpu.registers_mut();
+            registers.a = case.a;
+            registers.x = case.x;
+            registers.status = StatusFlags::from(case.status);
+            assert_eq!(cpu.step(), Ok(case.cycles), "{:02X?}", case.program);
+            let registers = cpu.registers();
+            assert_eq!(
+                (registers.a, registers.x, Byte::from(&registers.status), registers.p),
+                (case.expected_a, case.expected_x, case.expected_status, 0x0600 + case.program.len() as Byte2),
+                "{:02X?}",
+                case.program
+            );
+        }
+    }
+}
@@ -1,85 +1,123 @@
-use std::collections::HashSet;
-
-use lazy_static::lazy_static;
-
-use crate::{AddressingModeType, Byte, Registers};
-use crate::instructions::opcode::Memory;
+use crate::bus::Bus;
+use crate::{AddressingModeType, AddressingValue, Byte, Byte2, Registers};
+use crate::instructions::parser::DecodeError;
+use crate::instructions::opcode::{
+    add_with_carry, add_with_carry_decimal, compare, store, subtract_with_borrow_decimal,
+    update_zero_and_negative,
+};
 use crate::Opcode;
 
-pub const MASK: Byte = 0b111_000_01;
-
 /// "Group One" instructions
-pub fn decode_opcode_aaa_xxx_01(opcode: Byte) -> Opcode {
+pub fn decode_opcode_aaa_xxx_01(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
     match opcode & 0b111_000_11 {
-        0b000_000_01 => Opcode::ORA,
-        0b001_000_01 => Opcode::AND,
-        0b010_000_01 => Opcode::EOR,
-        0b011_000_01 => Opcode::ADC,
-        0b100_000_01 => Opcode::STA,
-        0b101_000_01 => Opcode::LDA,
-        0b110_000_01 => Opcode::CMP,
-        0b111_000_01 => Opcode::SBC,
-        op => unreachable!("{op}"),
+        0b000_000_01 => Ok(Opcode::ORA),
+        0b001_000_01 => Ok(Opcode::AND),
+        0b010_000_01 => Ok(Opcode::EOR),
+        0b011_000_01 => Ok(Opcode::ADC),
+        0b100_000_01 => Ok(Opcode::STA),
+        0b101_000_01 => Ok(Opcode::LDA),
+        0b110_000_01 => Ok(Opcode::CMP),
+        0b111_000_01 => Ok(Opcode::SBC),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
 }
 
-pub fn decode_addressing_mode_xxx_bbb_01(opcode: Byte) -> AddressingModeType {
+pub fn decode_addressing_mode_xxx_bbb_01(opcode: Byte, address: Byte2) -> Result<AddressingModeType, DecodeError> {
     match opcode & 0b000_111_11 {
-        0b000_000_01 => AddressingModeType::IndexedIndirect,
-        0b000_001_01 => AddressingModeType::ZeroPage,
-        0b000_010_01 => AddressingModeType::Immediate,
-        0b000_011_01 => AddressingModeType::Absolute,
-        0b000_100_01 => AddressingModeType::IndirectIndexed,
-        0b000_101_01 => AddressingModeType::ZeroPageIndexedWithX,
-        0b000_110_01 => AddressingModeType::AbsoluteIndexedWithY,
-        0b000_111_01 => AddressingModeType::AbsoluteIndexedWithX,
-        op => unreachable!("{op}"),
+        0b000_000_01 => Ok(AddressingModeType::IndexedIndirect),
+        0b000_001_01 => Ok(AddressingModeType::ZeroPage),
+        0b000_010_01 => Ok(AddressingModeType::Immediate),
+        0b000_011_01 => Ok(AddressingModeType::Absolute),
+        0b000_100_01 => Ok(AddressingModeType::IndirectIndexed),
+        0b000_101_01 => Ok(AddressingModeType::ZeroPageIndexedWithX),
+        0b000_110_01 => Ok(AddressingModeType::AbsoluteIndexedWithY),
+        0b000_111_01 => Ok(AddressingModeType::AbsoluteIndexedWithX),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
 }
 
-lazy_static! {
-    static ref ALL_ADDRESSING_MODES: HashSet<AddressingModeType> = HashSet::from_iter(vec![
-        AddressingModeType::Absolute,
-        AddressingModeType::ZeroPage,
-        AddressingModeType::Immediate,
-        AddressingModeType::Absolute,
-        AddressingModeType::IndirectIndexed,
-        AddressingModeType::ZeroPageIndexedWithX,
-        AddressingModeType::AbsoluteIndexedWithY,
-        AddressingModeType::AbsoluteIndexedWithY,
-    ]);
+/// Decodes a "Group One" opcode into its instruction and addressing mode
+pub fn decode_instruction_aaa_bbb_01(
+    opcode: Byte,
+    address: Byte2,
+) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    match (
+        decode_opcode_aaa_xxx_01(opcode, address)?,
+        decode_addressing_mode_xxx_bbb_01(opcode, address)?,
+    ) {
+        // There is no STA immediate, the slot reads its operand and does nothing
+        (Opcode::STA, AddressingModeType::Immediate) => Ok((Opcode::NOP, AddressingModeType::Immediate)),
+        decoded => Ok(decoded),
+    }
 }
 
 /// Add with Carry
-fn adc(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+pub fn adc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    add_with_carry(value, registers);
 }
 
-/// Logical AND
-fn and(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Add with Carry in decimal mode on the NMOS 6502
+pub fn adc_decimal(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    add_with_carry_decimal(value, false, registers);
 }
 
-/// Compare
-fn cmp(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Add with Carry in decimal mode on the 65C02
+pub fn adc_decimal_65c02(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    add_with_carry_decimal(value, true, registers);
 }
 
-fn eor(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
-
+/// Logical AND
+pub fn and(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.a &= addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.a, registers);
 }
 
-fn lda(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Compare
+pub fn cmp(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    compare(registers.a, value, registers);
+}
 
+/// Exclusive OR
+pub fn eor(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.a ^= addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.a, registers);
 }
 
-fn ora(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Load Accumulator
+pub fn lda(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.a = addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.a, registers);
+}
 
+/// Logical Inclusive OR
+pub fn ora(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    registers.a |= addressing_value.get_value(registers, bus).unwrap();
+    update_zero_and_negative(registers.a, registers);
 }
 
-fn sbc(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Subtract with Carry
+pub fn sbc(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    add_with_carry(!value, registers);
+}
 
+/// Subtract with Carry in decimal mode on the NMOS 6502
+pub fn sbc_decimal(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    subtract_with_borrow_decimal(value, false, registers);
 }
 
-fn sta(addressing_mode: AddressingModeType, mut registers: Registers, memory: Memory) {
+/// Subtract with Carry in decimal mode on the 65C02
+pub fn sbc_decimal_65c02(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    let value = addressing_value.get_value(registers, bus).unwrap();
+    subtract_with_borrow_decimal(value, true, registers);
+}
 
-}
\ No newline at end of file
+/// Store Accumulator
+pub fn sta(addressing_value: &AddressingValue, registers: &mut Registers, bus: &mut dyn Bus) {
+    store(addressing_value, registers.a, registers, bus);
+}
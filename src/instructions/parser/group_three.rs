@@ -1,30 +1,66 @@
-use crate::{AddressingModeType, Byte};
+use crate::instructions::parser::DecodeError;
+use crate::{AddressingModeType, Byte, Byte2};
 use crate::Opcode;
-pub const MASK: Byte = 0b111_000_00;
 
 /// "Group Three"" instructions
-pub fn decode_opcode_aaa_xxx_00(opcode: Byte) -> Opcode {
+pub fn decode_opcode_aaa_xxx_00(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
     match opcode & 0b111_000_11 {
-        0b001_000_00 => Opcode::BIT,
-        0b010_000_00 => Opcode::JMP,
-        0b011_000_00 => Opcode::JMP_ABS,
-        0b100_000_00 => Opcode::STY,
-        0b101_000_00 => Opcode::LDY,
-        0b110_000_00 => Opcode::CPY,
-        0b111_000_00 => Opcode::CPX,
-        op => unreachable!("{op}"),
+        0b001_000_00 => Ok(Opcode::BIT),
+        0b010_000_00 => Ok(Opcode::JMP),
+        0b011_000_00 => Ok(Opcode::JMP_ABS),
+        0b100_000_00 => Ok(Opcode::STY),
+        0b101_000_00 => Ok(Opcode::LDY),
+        0b110_000_00 => Ok(Opcode::CPY),
+        0b111_000_00 => Ok(Opcode::CPX),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
 }
 
-pub fn decode_addressing_mode_xxx_bbb_00(opcode: Byte) -> AddressingModeType {
+pub fn decode_addressing_mode_xxx_bbb_00(opcode: Byte, address: Byte2) -> Result<AddressingModeType, DecodeError> {
     match opcode & 0b000_111_11 {
-        0b000_000_00 => AddressingModeType::Immediate,
-        0b000_001_00 => AddressingModeType::ZeroPage,
-        0b000_011_00 => AddressingModeType::Absolute,
+        0b000_000_00 => Ok(AddressingModeType::Immediate),
+        0b000_001_00 => Ok(AddressingModeType::ZeroPage),
+        0b000_011_00 => Ok(AddressingModeType::Absolute),
         // With STX and LDX this is ZeroPageIndexedWithX
-        0b000_101_00 => AddressingModeType::ZeroPageIndexedWithX,
+        0b000_101_00 => Ok(AddressingModeType::ZeroPageIndexedWithX),
         // With LDX this is AbsoluteIndexedWithY,
-        0b000_111_00 => AddressingModeType::AbsoluteIndexedWithX,
-        op => unreachable!("{op}"),
+        0b000_111_00 => Ok(AddressingModeType::AbsoluteIndexedWithX),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
+    }
+}
+
+/// Decodes a "Group Three" opcode into its instruction and addressing mode
+pub fn decode_instruction_aaa_bbb_00(
+    opcode: Byte,
+    address: Byte2,
+) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    // Apart from BRK, PHP, BPL and CLC the first row only holds NOPs
+    if opcode & 0b111_000_00 == 0b000_000_00 {
+        return Ok((Opcode::NOP, decode_addressing_mode_xxx_bbb_00(opcode, address)?));
+    }
+    match (
+        decode_opcode_aaa_xxx_00(opcode, address)?,
+        decode_addressing_mode_xxx_bbb_00(opcode, address)?,
+    ) {
+        (Opcode::BIT, mode @ (AddressingModeType::ZeroPage | AddressingModeType::Absolute)) => {
+            Ok((Opcode::BIT, mode))
+        }
+        (Opcode::JMP, AddressingModeType::Absolute) => Ok((Opcode::JMP, AddressingModeType::Absolute)),
+        (Opcode::JMP_ABS, AddressingModeType::Absolute) => {
+            Ok((Opcode::JMP_ABS, AddressingModeType::AbsoluteIndirect))
+        }
+        (Opcode::STY, AddressingModeType::Immediate) => Ok((Opcode::NOP, AddressingModeType::Immediate)),
+        (Opcode::STY, AddressingModeType::AbsoluteIndexedWithX) => {
+            Ok((Opcode::SHY, AddressingModeType::AbsoluteIndexedWithX))
+        }
+        (
+            op @ (Opcode::CPY | Opcode::CPX),
+            mode @ (AddressingModeType::Immediate | AddressingModeType::ZeroPage | AddressingModeType::Absolute),
+        ) => Ok((op, mode)),
+        // The remaining slots read their operand and do nothing
+        (Opcode::BIT | Opcode::JMP | Opcode::JMP_ABS | Opcode::CPY | Opcode::CPX, mode) => {
+            Ok((Opcode::NOP, mode))
+        }
+        decoded => Ok(decoded),
     }
 }
@@ -1,32 +1,73 @@
-use crate::{AddressingModeType, Byte};
+use crate::instructions::parser::DecodeError;
+use crate::{AddressingModeType, Byte, Byte2};
 use crate::Opcode;
-pub const MASK: Byte = 0b111_000_10;
 
 /// "Group Two" instructions
-pub fn decode_opcode_aaa_xxx_10(opcode: Byte) -> Opcode {
+pub fn decode_opcode_aaa_xxx_10(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
     match opcode & 0b111_000_11 {
-        0b000_000_10 => Opcode::ASL,
-        0b001_000_10 => Opcode::ROL,
-        0b010_000_10 => Opcode::LSR,
-        0b011_000_10 => Opcode::ROR,
-        0b100_000_10 => Opcode::STX,
-        0b101_000_10 => Opcode::LDX,
-        0b110_000_10 => Opcode::DEC,
-        0b111_000_10 => Opcode::INC,
-        op => unreachable!("{op}"),
+        0b000_000_10 => Ok(Opcode::ASL),
+        0b001_000_10 => Ok(Opcode::ROL),
+        0b010_000_10 => Ok(Opcode::LSR),
+        0b011_000_10 => Ok(Opcode::ROR),
+        0b100_000_10 => Ok(Opcode::STX),
+        0b101_000_10 => Ok(Opcode::LDX),
+        0b110_000_10 => Ok(Opcode::DEC),
+        0b111_000_10 => Ok(Opcode::INC),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
 }
 
-pub fn decode_addressing_mode_xxx_bbb_10(opcode: Byte) -> AddressingModeType {
+pub fn decode_addressing_mode_xxx_bbb_10(opcode: Byte, address: Byte2) -> Result<AddressingModeType, DecodeError> {
     match opcode & 0b000_111_11 {
-        0b000_000_10 => AddressingModeType::Immediate,
-        0b000_001_10 => AddressingModeType::ZeroPage,
-        0b000_010_10 => AddressingModeType::Accumulator,
-        0b000_011_10 => AddressingModeType::Absolute,
+        0b000_000_10 => Ok(AddressingModeType::Immediate),
+        0b000_001_10 => Ok(AddressingModeType::ZeroPage),
+        0b000_010_10 => Ok(AddressingModeType::Accumulator),
+        0b000_011_10 => Ok(AddressingModeType::Absolute),
         // With STX and LDX this is ZeroPageIndexedWithX
-        0b000_101_10 => AddressingModeType::ZeroPageIndexedWithX,
+        0b000_101_10 => Ok(AddressingModeType::ZeroPageIndexedWithX),
         // With LDX this is AbsoluteIndexedWithY,
-        0b000_111_10 => AddressingModeType::AbsoluteIndexedWithX,
-        op => unreachable!("{op}"),
+        0b000_111_10 => Ok(AddressingModeType::AbsoluteIndexedWithX),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
-}
\ No newline at end of file
+}
+
+/// Decodes a "Group Two" opcode into its instruction and addressing mode
+pub fn decode_instruction_aaa_bbb_10(
+    opcode: Byte,
+    address: Byte2,
+) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    let unknown = DecodeError::UnknownOpcode { opcode, address };
+    match opcode & 0b000_111_00 {
+        // The (zp),Y slot locks up the CPU
+        0b000_100_00 => return Ok((Opcode::JAM, AddressingModeType::Implied)),
+        // Apart from TXS and TSX the abs,Y slot is a one-byte NOP
+        0b000_110_00 => return Ok((Opcode::NOP, AddressingModeType::Implied)),
+        _ => {}
+    }
+    match (
+        decode_opcode_aaa_xxx_10(opcode, address)?,
+        decode_addressing_mode_xxx_bbb_10(opcode, address)?,
+    ) {
+        (Opcode::LDX, AddressingModeType::Immediate) => {
+            Ok((Opcode::LDX, AddressingModeType::Immediate))
+        }
+        (Opcode::STX | Opcode::DEC | Opcode::INC, AddressingModeType::Immediate) => {
+            Ok((Opcode::NOP, AddressingModeType::Immediate))
+        }
+        (_, AddressingModeType::Immediate) => Ok((Opcode::JAM, AddressingModeType::Implied)),
+        // These slots are taken by TXA, TAX, DEX and NOP
+        (Opcode::STX | Opcode::LDX | Opcode::DEC | Opcode::INC, AddressingModeType::Accumulator) => {
+            Err(unknown)
+        }
+        (Opcode::STX, AddressingModeType::AbsoluteIndexedWithX) => {
+            Ok((Opcode::SHX, AddressingModeType::AbsoluteIndexedWithY))
+        }
+        (op @ (Opcode::STX | Opcode::LDX), AddressingModeType::ZeroPageIndexedWithX) => {
+            Ok((op, AddressingModeType::ZeroPageIndexedWithY))
+        }
+        (Opcode::LDX, AddressingModeType::AbsoluteIndexedWithX) => {
+            Ok((Opcode::LDX, AddressingModeType::AbsoluteIndexedWithY))
+        }
+        decoded => Ok(decoded),
+    }
+}
@@ -1,13 +1,25 @@
-use crate::Byte;
+use crate::instructions::parser::DecodeError;
+use crate::{AddressingModeType, Byte, Byte2};
 use crate::Opcode;
 
 ///  Interrupt and subroutine instructionss
-pub fn decode_opcode_interrupt_subroutine(opcode: Byte) -> Opcode {
+pub fn decode_opcode_interrupt_subroutine(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
     match opcode {
-        0b0000_0000 => Opcode::BRK,
-        0b0001_0000 => Opcode::JSR,
-        0b0010_0000 => Opcode::RTI,
-        0b0100_0000 => Opcode::RTS,
-        op => unreachable!("{op}"),
+        0b0000_0000 => Ok(Opcode::BRK),
+        0b0010_0000 => Ok(Opcode::JSR),
+        0b0100_0000 => Ok(Opcode::RTI),
+        0b0110_0000 => Ok(Opcode::RTS),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
+    }
+}
+
+/// Decodes an interrupt or subroutine opcode into its instruction and addressing mode
+pub fn decode_instruction_interrupt_subroutine(
+    opcode: Byte,
+    address: Byte2,
+) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    match decode_opcode_interrupt_subroutine(opcode, address)? {
+        Opcode::JSR => Ok((Opcode::JSR, AddressingModeType::Absolute)),
+        op => Ok((op, AddressingModeType::Implied)),
     }
 }
@@ -1,6 +1,111 @@
-mod group_one;
+use std::fmt;
+
+use crate::{AddressingModeType, Byte, Byte2, Opcode};
+
+pub mod group_one;
 mod group_two;
 mod group_three;
+pub mod group_four;
 mod conditionals;
 mod interrupts;
-mod others;
\ No newline at end of file
+mod others;
+mod cmos;
+
+pub use cmos::decode_opcode_65c02;
+
+/// Error returned when the bytes in memory do not form a known instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The byte at the given address is not a recognised opcode
+    UnknownOpcode { opcode: Byte, address: Byte2 },
+    /// The byte at the given address is an undocumented opcode and those are disabled
+    UndocumentedOpcode { opcode: Byte, address: Byte2 },
+    /// The CPU locked up on a JAM or STP opcode and needs a reset
+    Jammed { opcode: Byte, address: Byte2 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode { opcode, address } => {
+                write!(f, "unknown opcode ${opcode:02X} at ${address:04X}")
+            }
+            DecodeError::UndocumentedOpcode { opcode, address } => {
+                write!(f, "undocumented opcode ${opcode:02X} at ${address:04X}")
+            }
+            DecodeError::Jammed { opcode, address } => {
+                write!(f, "CPU jammed by opcode ${opcode:02X} at ${address:04X}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes the NMOS opcode byte found at `address` into its instruction and addressing mode
+pub fn decode_opcode(opcode: Byte, address: Byte2) -> Result<(Opcode, AddressingModeType), DecodeError> {
+    match opcode {
+        0b0000_0000 | 0b0010_0000 | 0b0100_0000 | 0b0110_0000 => {
+            interrupts::decode_instruction_interrupt_subroutine(opcode, address)
+        }
+        op if op & 0b000_1_1111 == 0b000_1_0000 => Ok((
+            conditionals::decode_opcode_xx_y_10000(op, address)?,
+            AddressingModeType::Relative,
+        )),
+        op if op & 0b0000_1111 == 0b0000_1000
+            || matches!(op, 0x8A | 0x9A | 0xAA | 0xBA | 0xCA | 0xEA) =>
+        {
+            Ok((others::decode_other_instruction(op, address)?, AddressingModeType::Implied))
+        }
+        op => match op & 0b000_000_11 {
+            0b01 => group_one::decode_instruction_aaa_bbb_01(op, address),
+            0b10 => group_two::decode_instruction_aaa_bbb_10(op, address),
+            0b00 => group_three::decode_instruction_aaa_bbb_00(op, address),
+            _ => group_four::decode_instruction_aaa_bbb_11(op, address),
+        },
+    }
+}
+
+/// Whether the byte is one of the undocumented NMOS opcodes, including the NOP slots other than
+/// $EA and the SBC duplicate at $EB
+pub fn is_undocumented_opcode(opcode: Byte) -> bool {
+    match decode_opcode(opcode, 0) {
+        Ok((Opcode::NOP, _)) => opcode != 0xEA,
+        Ok((Opcode::SBC, _)) => opcode == 0xEB,
+        Ok((op, _)) => op.is_undocumented(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_151_official_opcodes() {
+        let official = (0..=Byte::MAX)
+            .filter(|opcode| decode_opcode(*opcode, 0).is_ok() && !is_undocumented_opcode(*opcode))
+            .count();
+        assert_eq!(official, 151);
+    }
+
+    #[test]
+    fn decodes_every_byte_without_panicking() {
+        for opcode in 0..=Byte::MAX {
+            assert!(decode_opcode(opcode, 0).is_ok(), "${opcode:02X}");
+            assert!(decode_opcode_65c02(opcode, 0).is_ok(), "${opcode:02X}");
+        }
+        // The group three row without a mnemonic is an error rather than a panic
+        assert_eq!(
+            group_three::decode_opcode_aaa_xxx_00(0x04, 0x0600),
+            Err(DecodeError::UnknownOpcode { opcode: 0x04, address: 0x0600 })
+        );
+    }
+
+    #[test]
+    fn decodes_irregular_addressing_modes() {
+        assert_eq!(decode_opcode(0x6c, 0), Ok((Opcode::JMP_ABS, AddressingModeType::AbsoluteIndirect)));
+        assert_eq!(decode_opcode(0x96, 0), Ok((Opcode::STX, AddressingModeType::ZeroPageIndexedWithY)));
+        assert_eq!(decode_opcode(0xbe, 0), Ok((Opcode::LDX, AddressingModeType::AbsoluteIndexedWithY)));
+    }
+}
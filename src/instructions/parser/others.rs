@@ -1,30 +1,31 @@
-use crate::Byte;
+use crate::instructions::parser::DecodeError;
+use crate::{Byte, Byte2};
 use crate::Opcode;
 
-pub fn decode_other_instruction(opcode: Byte) -> Opcode {
+pub fn decode_other_instruction(opcode: Byte, address: Byte2) -> Result<Opcode, DecodeError> {
     match opcode {
-        0b0000_1000 => Opcode::PHP,
-        0b0010_1000 => Opcode::PLP,
-        0b0100_1000 => Opcode::PHA,
-        0b0110_1000 => Opcode::PLA,
-        0b1000_1000 => Opcode::DEY,
-        0b1010_1000 => Opcode::TAY,
-        0b1100_1000 => Opcode::INY,
-        0b1110_1000 => Opcode::INX,
-        0b0001_1000 => Opcode::CLC,
-        0b0011_1000 => Opcode::SEC,
-        0b0101_1000 => Opcode::CLI,
-        0b0111_1000 => Opcode::SEI,
-        0b1001_1000 => Opcode::TYA,
-        0b1011_1000 => Opcode::CLV,
-        0b1101_1000 => Opcode::CLD,
-        0b1111_1000 => Opcode::SED,
-        0b1000_1010 => Opcode::TXA,
-        0b1001_1010 => Opcode::TXS,
-        0b1010_1010 => Opcode::TAX,
-        0b1011_1010 => Opcode::TSX,
-        0b1100_1010 => Opcode::DEX,
-        0b1110_1010 => Opcode::NOP,
-        op => unreachable!("{op}"),
+        0b0000_1000 => Ok(Opcode::PHP),
+        0b0010_1000 => Ok(Opcode::PLP),
+        0b0100_1000 => Ok(Opcode::PHA),
+        0b0110_1000 => Ok(Opcode::PLA),
+        0b1000_1000 => Ok(Opcode::DEY),
+        0b1010_1000 => Ok(Opcode::TAY),
+        0b1100_1000 => Ok(Opcode::INY),
+        0b1110_1000 => Ok(Opcode::INX),
+        0b0001_1000 => Ok(Opcode::CLC),
+        0b0011_1000 => Ok(Opcode::SEC),
+        0b0101_1000 => Ok(Opcode::CLI),
+        0b0111_1000 => Ok(Opcode::SEI),
+        0b1001_1000 => Ok(Opcode::TYA),
+        0b1011_1000 => Ok(Opcode::CLV),
+        0b1101_1000 => Ok(Opcode::CLD),
+        0b1111_1000 => Ok(Opcode::SED),
+        0b1000_1010 => Ok(Opcode::TXA),
+        0b1001_1010 => Ok(Opcode::TXS),
+        0b1010_1010 => Ok(Opcode::TAX),
+        0b1011_1010 => Ok(Opcode::TSX),
+        0b1100_1010 => Ok(Opcode::DEX),
+        0b1110_1010 => Ok(Opcode::NOP),
+        _ => Err(DecodeError::UnknownOpcode { opcode, address }),
     }
 }
@@ -0,0 +1,369 @@
+use crate::bus::Bus;
+use crate::cpu::{CpuVariant, Interrupt};
+use crate::instructions::opcode::{bit_branch_condition, branch_condition, IRQ_VECTOR, NMI_VECTOR};
+use crate::{
+    AddressingValue, Byte, Byte2, Instruction, Opcode, PushSource, Registers, STACK_ADDRESS_SPACE,
+};
+
+/// What the CPU does on the bus during one cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusOperation {
+    Read,
+    /// Read whose value is thrown away, e.g. while an indexed address is being fixed up
+    DummyRead,
+    Write,
+    /// Write of the unmodified value that read-modify-write instructions do on the NMOS 6502
+    DummyWrite,
+}
+
+/// One bus access, the CPU makes exactly one per cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub operation: BusOperation,
+    pub address: Byte2,
+    /// Value read or written
+    pub value: Byte,
+}
+
+/// How an instruction uses its memory operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+/// How the operand of an instruction with a memory addressing mode is accessed
+pub fn access_kind(opcode: Opcode) -> AccessKind {
+    match opcode {
+        Opcode::STA
+        | Opcode::STX
+        | Opcode::STY
+        | Opcode::STZ
+        | Opcode::SAX
+        | Opcode::SHA
+        | Opcode::SHX
+        | Opcode::SHY
+        | Opcode::TAS => AccessKind::Write,
+        Opcode::ASL
+        | Opcode::LSR
+        | Opcode::ROL
+        | Opcode::ROR
+        | Opcode::INC
+        | Opcode::DEC
+        | Opcode::SLO
+        | Opcode::RLA
+        | Opcode::SRE
+        | Opcode::RRA
+        | Opcode::DCP
+        | Opcode::ISC
+        | Opcode::TRB
+        | Opcode::TSB
+        | Opcode::RMB(_)
+        | Opcode::SMB(_) => AccessKind::ReadModifyWrite,
+        _ => AccessKind::Read,
+    }
+}
+
+/// Bus accesses of an instruction in progress, built by peeking at the bus at its start
+struct Schedule<'a> {
+    accesses: Vec<BusAccess>,
+    bus: &'a dyn Bus,
+    variant: CpuVariant,
+}
+
+impl<'a> Schedule<'a> {
+    fn new(variant: CpuVariant, bus: &'a dyn Bus) -> Self {
+        Schedule {
+            accesses: Vec::with_capacity(8),
+            bus,
+            variant,
+        }
+    }
+
+    fn push(&mut self, operation: BusOperation, address: Byte2, value: Byte) {
+        self.accesses.push(BusAccess { operation, address, value });
+    }
+
+    fn read(&mut self, address: Byte2) -> Byte {
+        let value = self.bus.peek(address);
+        self.push(BusOperation::Read, address, value);
+        value
+    }
+
+    fn read_address(&mut self, low: Byte2, high: Byte2) -> Byte2 {
+        let low = self.read(low);
+        let high = self.read(high);
+        ((high as Byte2) << 8) | low as Byte2
+    }
+
+    fn dummy_read(&mut self, address: Byte2) {
+        let value = self.bus.peek(address);
+        self.push(BusOperation::DummyRead, address, value);
+    }
+
+    fn write(&mut self, address: Byte2, value: Byte) {
+        self.push(BusOperation::Write, address, value);
+    }
+
+    /// Accesses the operand once its address is known
+    fn operand(&mut self, kind: AccessKind, address: Byte2) {
+        match kind {
+            AccessKind::Read => {
+                self.read(address);
+            }
+            // The written value is only known once the instruction has executed
+            AccessKind::Write => self.write(address, 0),
+            AccessKind::ReadModifyWrite => {
+                let value = self.read(address);
+                // The 65C02 reads the operand again instead of writing it back unmodified
+                match self.variant.is_cmos() {
+                    true => self.dummy_read(address),
+                    false => self.push(BusOperation::DummyWrite, address, value),
+                }
+                self.write(address, 0);
+            }
+        }
+    }
+
+    /// Pushes the return address and status, then fetches the vector
+    fn interrupt(&mut self, return_address: Byte2, status: Byte, stack_pointer: Byte, vector: Byte2) {
+        self.write(stack_address(stack_pointer), (return_address >> 8) as Byte);
+        self.write(stack_address(stack_pointer.wrapping_sub(1)), return_address as Byte);
+        self.write(stack_address(stack_pointer.wrapping_sub(2)), status);
+        self.read_address(vector, vector.wrapping_add(1));
+    }
+
+    /// Hands out the accesses, one per cycle the instruction-stepped core counts
+    fn finish(self, cycles: Byte) -> Vec<BusAccess> {
+        debug_assert_eq!(self.accesses.len(), cycles as usize, "{:02X?}", self.accesses);
+        self.accesses
+    }
+}
+
+fn stack_address(stack_pointer: Byte) -> Byte2 {
+    STACK_ADDRESS_SPACE.start | stack_pointer as Byte2
+}
+
+/// Lists the bus accesses of the instruction at the program counter in hardware order
+///
+/// The last access is where the instruction takes effect, the value of a write there is only
+/// known after executing it. `cycles` is the cycle count of the instruction-stepped core, which
+/// the list always matches.
+pub fn instruction_schedule(
+    instruction: &Instruction,
+    cycles: Byte,
+    variant: CpuVariant,
+    registers: &Registers,
+    bus: &dyn Bus,
+) -> Vec<BusAccess> {
+    let mut schedule = Schedule::new(variant, bus);
+    let address = registers.p;
+    let operand = address.wrapping_add(1);
+    let next = address.wrapping_add(instruction.length as Byte2);
+    let s = registers.s;
+    let opcode = schedule.read(address);
+    let kind = access_kind(instruction.opcode);
+    match (instruction.opcode, &instruction.addressing_value) {
+        // The NOPs in the unused xxxx_x011 slots of the 65C02 are done after the opcode fetch
+        (Opcode::NOP, AddressingValue::Implied) if variant.is_cmos() && opcode != 0xEA => {}
+        // $5C reads from $FF00 plus its low operand byte, then idles on $FFFF
+        (Opcode::NOP, AddressingValue::Absolute(absolute)) if variant.is_cmos() && opcode == 0x5C => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+            schedule.dummy_read(0xff00 | (absolute & 0x00ff));
+            for _ in 0..4 {
+                schedule.dummy_read(0xffff);
+            }
+        }
+        (Opcode::WAI | Opcode::STP, _) => {
+            schedule.dummy_read(operand);
+            schedule.dummy_read(operand);
+        }
+        (Opcode::BRK, _) => {
+            schedule.read(operand);
+            let status = registers.status.to_pushed_byte(PushSource::Brk);
+            schedule.interrupt(address.wrapping_add(2), status, s, IRQ_VECTOR);
+        }
+        (Opcode::JSR, _) => {
+            schedule.read(operand);
+            schedule.dummy_read(stack_address(s));
+            let return_address = address.wrapping_add(2);
+            schedule.write(stack_address(s), (return_address >> 8) as Byte);
+            schedule.write(stack_address(s.wrapping_sub(1)), return_address as Byte);
+            schedule.read(address.wrapping_add(2));
+        }
+        (Opcode::RTS, _) => {
+            schedule.dummy_read(operand);
+            schedule.dummy_read(stack_address(s));
+            let return_address = schedule.read_address(
+                stack_address(s.wrapping_add(1)),
+                stack_address(s.wrapping_add(2)),
+            );
+            schedule.dummy_read(return_address);
+        }
+        (Opcode::RTI, _) => {
+            schedule.dummy_read(operand);
+            schedule.dummy_read(stack_address(s));
+            schedule.read(stack_address(s.wrapping_add(1)));
+            schedule.read_address(stack_address(s.wrapping_add(2)), stack_address(s.wrapping_add(3)));
+        }
+        (Opcode::PHA | Opcode::PHP | Opcode::PHX | Opcode::PHY, _) => {
+            schedule.dummy_read(operand);
+            let value = match instruction.opcode {
+                Opcode::PHA => registers.a,
+                Opcode::PHX => registers.x,
+                Opcode::PHY => registers.y,
+                _ => registers.status.to_pushed_byte(PushSource::Php),
+            };
+            schedule.write(stack_address(s), value);
+        }
+        (Opcode::PLA | Opcode::PLP | Opcode::PLX | Opcode::PLY, _) => {
+            schedule.dummy_read(operand);
+            schedule.dummy_read(stack_address(s));
+            schedule.read(stack_address(s.wrapping_add(1)));
+        }
+        (Opcode::JMP, _) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+        }
+        (Opcode::JMP_ABS, AddressingValue::AbsoluteIndirect(pointer)) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+            let high = match variant.is_cmos() {
+                true => {
+                    schedule.dummy_read(operand.wrapping_add(1));
+                    pointer.wrapping_add(1)
+                }
+                false => (pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff),
+            };
+            schedule.read_address(*pointer, high);
+        }
+        (Opcode::JMP_ABS, AddressingValue::AbsoluteIndexedIndirect(base)) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+            schedule.dummy_read(operand.wrapping_add(1));
+            let pointer = base.wrapping_add(registers.x as Byte2);
+            schedule.read_address(pointer, pointer.wrapping_add(1));
+        }
+        (opcode, AddressingValue::Relative(offset)) => {
+            schedule.read(operand);
+            if branch_condition(opcode, &registers.status).unwrap() {
+                branch(&mut schedule, next, *offset);
+            }
+        }
+        (opcode, AddressingValue::ZeroPageRelative(zero_page, offset)) => {
+            schedule.read(operand);
+            schedule.read(operand.wrapping_add(1));
+            let value = schedule.read(*zero_page as Byte2);
+            schedule.dummy_read(*zero_page as Byte2);
+            if bit_branch_condition(opcode, value).unwrap() {
+                branch(&mut schedule, next, *offset);
+            }
+        }
+        (_, AddressingValue::Accumulator | AddressingValue::Implied) => {
+            schedule.dummy_read(operand);
+        }
+        (_, AddressingValue::Immediate(_)) => {
+            schedule.read(operand);
+        }
+        (_, AddressingValue::ZeroPage(zero_page)) => {
+            schedule.read(operand);
+            schedule.operand(kind, *zero_page as Byte2);
+        }
+        (_, AddressingValue::ZeroPageIndexedWithX(zero_page)) => {
+            zero_page_indexed(&mut schedule, kind, operand, *zero_page, registers.x);
+        }
+        (_, AddressingValue::ZeroPageIndexedWithY(zero_page)) => {
+            zero_page_indexed(&mut schedule, kind, operand, *zero_page, registers.y);
+        }
+        (_, AddressingValue::Absolute(absolute)) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+            schedule.operand(kind, *absolute);
+        }
+        (opcode, AddressingValue::AbsoluteIndexedWithX(base)) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+            indexed(&mut schedule, opcode, kind, operand.wrapping_add(1), *base, registers.x);
+        }
+        (opcode, AddressingValue::AbsoluteIndexedWithY(base)) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+            indexed(&mut schedule, opcode, kind, operand.wrapping_add(1), *base, registers.y);
+        }
+        (_, AddressingValue::IndexedIndirect(zero_page)) => {
+            schedule.read(operand);
+            schedule.dummy_read(*zero_page as Byte2);
+            let pointer = zero_page.wrapping_add(registers.x);
+            let target = schedule.read_address(pointer as Byte2, pointer.wrapping_add(1) as Byte2);
+            schedule.operand(kind, target);
+        }
+        (opcode, AddressingValue::IndirectIndexed(zero_page)) => {
+            schedule.read(operand);
+            let base = schedule.read_address(*zero_page as Byte2, zero_page.wrapping_add(1) as Byte2);
+            indexed(&mut schedule, opcode, kind, operand, base, registers.y);
+        }
+        (_, AddressingValue::ZeroPageIndirect(zero_page)) => {
+            schedule.read(operand);
+            let target = schedule.read_address(*zero_page as Byte2, zero_page.wrapping_add(1) as Byte2);
+            schedule.operand(kind, target);
+        }
+        (_, AddressingValue::AbsoluteIndirect(_) | AddressingValue::AbsoluteIndexedIndirect(_)) => {
+            schedule.read_address(operand, operand.wrapping_add(1));
+        }
+    }
+    // The 65C02 spends a cycle fixing up the flags of decimal arithmetic, reading the next opcode
+    if variant.is_cmos() && registers.status.get_d() && matches!(instruction.opcode, Opcode::ADC | Opcode::SBC) {
+        schedule.dummy_read(next);
+    }
+    schedule.finish(cycles)
+}
+
+/// Lists the bus accesses of servicing a hardware interrupt
+pub fn interrupt_schedule(interrupt: Interrupt, registers: &Registers, bus: &dyn Bus) -> Vec<BusAccess> {
+    let mut schedule = Schedule::new(CpuVariant::default(), bus);
+    // The opcode fetch is thrown away and the program counter is not incremented
+    schedule.dummy_read(registers.p);
+    schedule.dummy_read(registers.p);
+    let (source, vector) = match interrupt {
+        Interrupt::Nmi => (PushSource::Nmi, NMI_VECTOR),
+        Interrupt::Irq => (PushSource::Irq, IRQ_VECTOR),
+    };
+    let status = registers.status.to_pushed_byte(source);
+    schedule.interrupt(registers.p, status, registers.s, vector);
+    schedule.accesses
+}
+
+/// A taken branch reads the next opcode while adding the offset, and again from the wrong page
+/// while fixing the high byte
+fn branch(schedule: &mut Schedule, next: Byte2, offset: Byte) {
+    schedule.dummy_read(next);
+    let target = next.wrapping_add(offset as i8 as Byte2);
+    if target & 0xff00 != next & 0xff00 {
+        schedule.dummy_read((next & 0xff00) | (target & 0x00ff));
+    }
+}
+
+fn zero_page_indexed(schedule: &mut Schedule, kind: AccessKind, operand: Byte2, zero_page: Byte, index: Byte) {
+    schedule.read(operand);
+    schedule.dummy_read(zero_page as Byte2);
+    schedule.operand(kind, zero_page.wrapping_add(index) as Byte2);
+}
+
+/// Adds the index to the low byte first, reading from the unfixed address while the high byte is
+/// corrected, which reads skip when no page was crossed
+fn indexed(
+    schedule: &mut Schedule,
+    opcode: Opcode,
+    kind: AccessKind,
+    last_operand: Byte2,
+    base: Byte2,
+    index: Byte,
+) {
+    let target = base.wrapping_add(index as Byte2);
+    let page_crossed = target & 0xff00 != base & 0xff00;
+    let cmos_shift = schedule.variant.is_cmos()
+        && matches!(opcode, Opcode::ASL | Opcode::LSR | Opcode::ROL | Opcode::ROR);
+    if page_crossed || (kind != AccessKind::Read && !cmos_shift) {
+        // The 65C02 reads the last operand byte again instead of the unfixed address
+        match schedule.variant.is_cmos() {
+            true => schedule.dummy_read(last_operand),
+            false => schedule.dummy_read((base & 0xff00) | (target & 0x00ff)),
+        }
+    }
+    schedule.operand(kind, target);
+}
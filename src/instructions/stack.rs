@@ -0,0 +1,178 @@
+use crate::bus::Bus;
+use crate::{
+    get_value_from_absolute, set_value_to_absolute, Byte, Byte2, Instruction, Opcode, Registers,
+    STACK_ADDRESS_SPACE,
+};
+
+/// Address of the stack slot the stack pointer refers to
+fn stack_address(stack_pointer: Byte) -> Byte2 {
+    STACK_ADDRESS_SPACE.start | stack_pointer as Byte2
+}
+
+/// Pushes a byte, wrapping from $0100 to $01FF
+pub fn push(value: Byte, registers: &mut Registers, bus: &mut dyn Bus) {
+    set_value_to_absolute(stack_address(registers.s), value, bus);
+    registers.s = registers.s.wrapping_sub(1);
+}
+
+/// Pulls a byte, wrapping from $01FF to $0100
+pub fn pull(registers: &mut Registers, bus: &mut dyn Bus) -> Byte {
+    registers.s = registers.s.wrapping_add(1);
+    get_value_from_absolute(stack_address(registers.s), bus)
+}
+
+/// Pushes an address high byte first so it can be pulled back low byte first
+pub fn push_address(address: Byte2, registers: &mut Registers, bus: &mut dyn Bus) {
+    push((address >> 8) as Byte, registers, bus);
+    push(address as Byte, registers, bus);
+}
+
+pub fn pull_address(registers: &mut Registers, bus: &mut dyn Bus) -> Byte2 {
+    let low = pull(registers, bus);
+    let high = pull(registers, bus);
+    ((high as Byte2) << 8) | low as Byte2
+}
+
+/// Suspicious stack activity reported by a [`StackMonitor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackEvent {
+    /// A push wrapped the stack pointer from $00 to $FF
+    Overflow { address: Byte2 },
+    /// A pull wrapped the stack pointer from $FF to $00
+    Underflow { address: Byte2 },
+    /// RTS or RTI did not return to where the innermost JSR or interrupt came from
+    UnbalancedReturn {
+        address: Byte2,
+        /// Return address of the innermost JSR or interrupt, `None` if there was none
+        expected: Option<Byte2>,
+        actual: Byte2,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CallFrame {
+    Subroutine(Byte2),
+    Interrupt(Byte2),
+}
+
+/// Tracks pushes, pulls and call frames and reports [`StackEvent`]s to a listener
+pub struct StackMonitor {
+    listener: Box<dyn FnMut(StackEvent)>,
+    /// JSRs and interrupts that have not returned yet, innermost last
+    frames: Vec<CallFrame>,
+}
+
+impl StackMonitor {
+    pub fn new(listener: impl FnMut(StackEvent) + 'static) -> Self {
+        StackMonitor {
+            listener: Box::new(listener),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Checks an instruction that started at `address` with the stack pointer at
+    /// `stack_pointer`, after it has executed
+    pub fn observe_instruction(
+        &mut self,
+        instruction: &Instruction,
+        address: Byte2,
+        stack_pointer: Byte,
+        registers: &Registers,
+    ) {
+        let pushed: i16 = match instruction.opcode {
+            Opcode::PHA | Opcode::PHP | Opcode::PHX | Opcode::PHY => 1,
+            Opcode::JSR => 2,
+            Opcode::BRK => 3,
+            Opcode::PLA | Opcode::PLP | Opcode::PLX | Opcode::PLY => -1,
+            Opcode::RTS => -2,
+            Opcode::RTI => -3,
+            _ => 0,
+        };
+        self.check_wraparound(address, stack_pointer, pushed);
+        match instruction.opcode {
+            Opcode::JSR => {
+                let return_address = address.wrapping_add(instruction.length as Byte2);
+                self.frames.push(CallFrame::Subroutine(return_address));
+            }
+            // BRK returns past its padding byte
+            Opcode::BRK => self.frames.push(CallFrame::Interrupt(address.wrapping_add(2))),
+            Opcode::RTS => self.check_return(address, registers.p, CallFrame::Subroutine),
+            Opcode::RTI => self.check_return(address, registers.p, CallFrame::Interrupt),
+            // Reloading the stack pointer abandons every frame on the stack
+            Opcode::TXS => self.frames.clear(),
+            _ => {}
+        }
+    }
+
+    /// Checks an NMI or IRQ that interrupted execution at `address`
+    pub fn observe_interrupt(&mut self, address: Byte2, stack_pointer: Byte) {
+        self.check_wraparound(address, stack_pointer, 3);
+        self.frames.push(CallFrame::Interrupt(address));
+    }
+
+    fn check_wraparound(&mut self, address: Byte2, stack_pointer: Byte, pushed: i16) {
+        let stack_pointer = stack_pointer as i16 - pushed;
+        if stack_pointer < 0 {
+            (self.listener)(StackEvent::Overflow { address });
+        } else if stack_pointer > Byte::MAX as i16 {
+            (self.listener)(StackEvent::Underflow { address });
+        }
+    }
+
+    fn check_return(&mut self, address: Byte2, actual: Byte2, kind: fn(Byte2) -> CallFrame) {
+        let frame = self.frames.pop();
+        if frame != Some(kind(actual)) {
+            let expected = frame.map(|frame| match frame {
+                CallFrame::Subroutine(return_address) | CallFrame::Interrupt(return_address) => {
+                    return_address
+                }
+            });
+            (self.listener)(StackEvent::UnbalancedReturn {
+                address,
+                expected,
+                actual,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::bus::Memory;
+    use crate::cpu::{Cpu, CpuVariant};
+
+    /// Runs the one-byte instruction `opcode` on a 65C02 with the stack pointer at
+    /// `stack_pointer`, returning the events reported
+    fn run(opcode: Byte, stack_pointer: Byte) -> Vec<StackEvent> {
+        let mut memory = Memory::new();
+        memory.load(0x0600, &[opcode]);
+        let mut cpu = Cpu::new(memory, 0x0600);
+        cpu.set_variant(CpuVariant::Wdc65C02);
+        cpu.registers_mut().s = stack_pointer;
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let listener = Rc::clone(&events);
+        cpu.set_stack_monitor(Some(StackMonitor::new(move |event| listener.borrow_mut().push(event))));
+        cpu.step().unwrap();
+        events.take()
+    }
+
+    #[test]
+    fn pushes_wrapping_the_stack_pointer_overflow() {
+        for opcode in [0x48, 0xda, 0x5a] {
+            assert_eq!(run(opcode, 0x00), [StackEvent::Overflow { address: 0x0600 }], "{opcode:02X}");
+            assert_eq!(run(opcode, 0x01), [], "{opcode:02X}");
+        }
+    }
+
+    #[test]
+    fn pulls_wrapping_the_stack_pointer_underflow() {
+        for opcode in [0x68, 0xfa, 0x7a] {
+            assert_eq!(run(opcode, 0xff), [StackEvent::Underflow { address: 0x0600 }], "{opcode:02X}");
+            assert_eq!(run(opcode, 0xfe), [], "{opcode:02X}");
+        }
+    }
+}
@@ -0,0 +1,1053 @@
+//! 6502 family CPU core for emulating the NES and other 6502 machines
+//!
+//! [`cpu::Cpu`] executes through a [`bus::Bus`], the decoders live in
+//! [`instructions::parser`], instruction facts in [`instructions::metadata`] and
+//! [`disassembler`] turns memory back into assembly. [`nes`] holds the hardware of the console
+//! around the CPU.
+
+// Opcode literals are grouped by their AAA_BBB_CC bit fields rather than by nibble
+#![allow(clippy::unusual_byte_groupings)]
+
+use std::fmt;
+use std::ops::Range;
+
+use bitvec::field::BitField;
+use bitvec::prelude::BitArray;
+use paste::paste;
+
+use crate::cpu::CpuVariant;
+use crate::instructions::dispatch::dispatch_table;
+use crate::bus::Bus;
+use crate::instructions::opcode::{handler, Handler};
+use crate::instructions::parser::DecodeError;
+
+pub mod bus;
+pub mod cpu;
+pub mod disassembler;
+pub mod instructions;
+pub mod nes;
+pub mod power;
+
+// https://llx.com/Neil/a2/opcodes.html
+
+// https://en.wikipedia.org/wiki/MOS_Technology_6502#Addressing
+// Addressing modes also include implied (1-byte instructions); absolute (3 bytes); indexed absolute
+// (3 bytes); indexed zero-page (2 bytes); relative (2 bytes); accumulator (1); indirect,x and
+// indirect,y (2); and immediate (2). Absolute mode is a general-purpose mode. Branch instructions
+// use a signed 8-bit offset relative to the instruction after the branch; the numerical range
+// −128..127 therefore translates to 128 bytes backward and 127 bytes forward from the instruction
+// following the branch (which is 126 bytes backward and 129 bytes forward from the start of the
+// branch instruction). Accumulator mode uses the accumulator as an effective address and does not
+// need any operand data. Immediate mode uses an 8-bit literal operand.
+
+// 6502 instruction operation codes (opcodes) are 8 bits long and have the general form AAABBBCC,
+// where AAA and CC define the opcode.rs, and BBB defines the addressing mode.
+
+// Page size: 256 bytes
+
+const STACK_ADDRESS_SPACE: Range<u16> = Range {
+    start: 0x100,
+    end: 0x0200,
+};
+
+pub type Byte = u8;
+pub type Byte2 = u16;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct StatusFlags(BitArray);
+
+macro_rules! flag {
+    ($name:literal, $shorthand:ident, $pos:expr) => {
+        paste! {
+            #[doc= " get " $name]
+            pub fn [<get_ $shorthand>](&self) -> bool {
+                self.0[$pos]
+            }
+
+            #[doc=" replace " $name]
+            pub fn [<replace_ $shorthand>](&mut self, val: bool) -> bool {
+                self.0.replace($pos, val)
+            }
+        }
+    };
+}
+
+/// Reserved bit 5, which is not connected to a flag and always reads as set
+const UNUSED_FLAG: usize = 5;
+const BREAK_FLAG: usize = 4;
+
+impl StatusFlags {
+    pub fn new() -> Self {
+        let mut flags = StatusFlags(bitvec::bitarr!(0; 8));
+        flags.0.set(UNUSED_FLAG, true);
+        flags
+    }
+    // from bit 7 to bit 0 these are the negative (N), overflow (V), reserved, break (B),
+    // decimal (D), interrupt disable (I), zero (Z) and carry (C) flag
+
+    flag!("negative", n, 7);
+    flag!("overflow", v, 6);
+    flag!("break", b, 4);
+    flag!("decimal", d, 3);
+    flag!("interrupt disable", i, 2);
+    flag!("zero", z, 1);
+    flag!("carry", c, 0);
+
+    /// Packs the flags as they are pushed, with B set only for BRK and PHP
+    fn to_pushed_byte(&self, source: PushSource) -> Byte {
+        let mut flags = StatusFlags::from(Byte::from(self));
+        flags.0.set(BREAK_FLAG, source.sets_break_flag());
+        Byte::from(&flags)
+    }
+
+    /// Loads the flags pulled by PLP or RTI, which cannot change B or the unused bit
+    fn replace_from_pulled_byte(&mut self, value: Byte) {
+        let break_flag = self.get_b();
+        *self = StatusFlags::from(value);
+        self.replace_b(break_flag);
+    }
+}
+
+impl From<Byte> for StatusFlags {
+    fn from(value: Byte) -> Self {
+        let mut flags = StatusFlags(bitvec::bitarr!(0; 8));
+        flags.0[..8].store_le(value);
+        flags.0.set(UNUSED_FLAG, true);
+        flags
+    }
+}
+
+impl From<&StatusFlags> for Byte {
+    fn from(flags: &StatusFlags) -> Self {
+        flags.0[..8].load_le()
+    }
+}
+
+impl Default for StatusFlags {
+    fn default() -> Self {
+        StatusFlags::new()
+    }
+}
+
+impl fmt::Debug for StatusFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StatusFlags").field(&format_args!("{self}")).finish()
+    }
+}
+
+/// Formats the flags as `NV-BDIZC`, upper case when set and lower case when clear
+impl fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bit, name) in (0..8).rev().zip("NV-BDIZC".chars()) {
+            if bit == UNUSED_FLAG || self.0[bit] {
+                write!(f, "{name}")?;
+            } else {
+                write!(f, "{}", name.to_ascii_lowercase())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What caused the status flags to be pushed, which decides the pushed B flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushSource {
+    Brk,
+    Php,
+    Irq,
+    Nmi,
+}
+
+impl PushSource {
+    fn sets_break_flag(self) -> bool {
+        matches!(self, PushSource::Brk | PushSource::Php)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers {
+    /// accumulator
+    pub a: Byte,
+    /// Index register X
+    pub x: Byte,
+    /// Index register Y
+    pub y: Byte,
+    /// Status byte flags
+    pub status: StatusFlags,
+    /// Stack pointer
+    pub s: Byte,
+    /// Program counter
+    pub p: Byte2,
+}
+
+impl Registers {
+    /// Cleared registers with the stack pointer at the top of the stack
+    pub fn new(program_counter: Byte2) -> Self {
+        Registers {
+            a: 0,
+            x: 0,
+            y: 0,
+            status: StatusFlags::new(),
+            s: 0xff,
+            p: program_counter,
+        }
+    }
+
+    /// State after power-on, once the reset sequence has taken S from $00 down to $FD and set I
+    pub fn power_on(program_counter: Byte2) -> Self {
+        let mut registers = Registers::new(program_counter);
+        registers.s = 0xfd;
+        registers.status.replace_i(true);
+        registers
+    }
+}
+
+/// Decoded instruction ready to be executed
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    /// Addressing mode with the operand bytes fetched from memory
+    pub addressing_value: AddressingValue,
+    /// Length of the instruction in bytes, including the opcode
+    pub length: Byte,
+    /// Cycles taken without page crossing or branch penalties
+    pub cycles: Byte,
+    pub handler: Handler,
+    /// Handler used instead while the decimal flag is set
+    pub decimal_handler: Handler,
+}
+
+impl Instruction {
+    /// One-byte, two-cycle NOP standing in for an unknown opcode that is stepped over
+    fn skipped_opcode() -> Self {
+        Instruction {
+            opcode: Opcode::NOP,
+            addressing_value: AddressingValue::Implied,
+            length: 1,
+            cycles: 2,
+            handler: handler(Opcode::NOP, CpuVariant::default(), false),
+            decimal_handler: handler(Opcode::NOP, CpuVariant::default(), true),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingValue {
+    /// Accumulator: A
+    /// Like implied addressing but object being the accumulator
+    Accumulator,
+    /// Implied
+    /// No memory reference
+    Implied,
+    /// Immediate: #aa
+    /// The given value is used as is
+    Immediate(Byte),
+    /// Absolute: aaaa
+    /// The given value is the memory address of the 8 bit value to use
+    Absolute(Byte2),
+    /// Zero Page: aa
+    /// Reference to one of the first 256 memory locations
+    ZeroPage(Byte),
+    /// Relative: aaaa
+    Relative(Byte),
+    /// Indirect Absolute: (aaaa)
+    /// Used by JMP: Uses the given address as a pointer to the low part of a 16-bit address
+    AbsoluteIndirect(Byte2),
+    /// Absolute Indexed with X: aaaa,X
+    /// The given address is used as a base and the value of X register is added as an offset
+    AbsoluteIndexedWithX(Byte2),
+    /// Absolute Indexed with Y: aaaa,Y
+    /// The given address is used as a base and the value of Y register is added as an offset
+    AbsoluteIndexedWithY(Byte2),
+    /// Zero Page Indexed with X: aa,X
+    /// As absolute indexed with X but in the Zero page memory
+    ZeroPageIndexedWithX(Byte),
+    /// Zero Page Indexed with Y: aa,Y
+    /// As absolute indexed with Y but in the Zero page memory
+    ZeroPageIndexedWithY(Byte),
+    /// Indexed Indirect Addressing: (aa,X)
+    /// Given location +X points to the 16-bit address containing a 16-bit pointer to the value
+    IndexedIndirect(Byte),
+    /// Indirect Indexed Addressing: (aa),Y
+    /// Given location points to a 16-bit address containing a 16 bit pointer to the value after Y is added to it
+    IndirectIndexed(Byte),
+    /// Zero Page Indirect: (aa)
+    /// 65C02 only: Given location points to a 16-bit pointer to the value
+    ZeroPageIndirect(Byte),
+    /// Absolute Indexed Indirect: (aaaa,X)
+    /// 65C02 only: Used by JMP: The given address +X points to a 16-bit address
+    AbsoluteIndexedIndirect(Byte2),
+    /// Zero Page Relative: aa,aaaa
+    /// 65C02 only: Used by BBR and BBS: The zero page location to test and the branch offset
+    ZeroPageRelative(Byte, Byte),
+}
+
+#[derive(Debug, Clone, Copy, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub enum AddressingModeType {
+    Accumulator,
+    Implied,
+    Immediate,
+    Absolute,
+    ZeroPage,
+    Relative,
+    AbsoluteIndirect,
+    AbsoluteIndexedWithX,
+    AbsoluteIndexedWithY,
+    ZeroPageIndexedWithX,
+    ZeroPageIndexedWithY,
+    IndexedIndirect,
+    IndirectIndexed,
+    ZeroPageIndirect,
+    AbsoluteIndexedIndirect,
+    ZeroPageRelative,
+}
+
+impl AddressingModeType {
+    /// Number of operand bytes following the opcode
+    pub fn operand_length(&self) -> Byte {
+        match self {
+            AddressingModeType::Accumulator | AddressingModeType::Implied => 0,
+            AddressingModeType::Immediate
+            | AddressingModeType::ZeroPage
+            | AddressingModeType::Relative
+            | AddressingModeType::ZeroPageIndexedWithX
+            | AddressingModeType::ZeroPageIndexedWithY
+            | AddressingModeType::IndexedIndirect
+            | AddressingModeType::IndirectIndexed
+            | AddressingModeType::ZeroPageIndirect => 1,
+            AddressingModeType::Absolute
+            | AddressingModeType::ZeroPageRelative
+            | AddressingModeType::AbsoluteIndexedIndirect
+            | AddressingModeType::AbsoluteIndirect
+            | AddressingModeType::AbsoluteIndexedWithX
+            | AddressingModeType::AbsoluteIndexedWithY => 2,
+        }
+    }
+}
+
+pub struct AddressingModeBuilder {
+    addressing_mode: AddressingModeType
+}
+
+impl AddressingModeBuilder {
+    pub fn new(addressing_mode: AddressingModeType) -> Self {
+        AddressingModeBuilder { addressing_mode }
+    }
+
+    /// Builds the addressing value from the operand bytes starting at `address`
+    pub fn build(self, address: Byte2, bus: &mut dyn Bus) -> AddressingValue {
+        let byte = get_value_from_absolute(address, bus);
+        let mut word = || {
+            let high = get_value_from_absolute(address.wrapping_add(1), bus);
+            ((high as Byte2) << 8) | byte as Byte2
+        };
+        match self.addressing_mode {
+            AddressingModeType::Accumulator => AddressingValue::Accumulator,
+            AddressingModeType::Implied => AddressingValue::Implied,
+            AddressingModeType::Immediate => AddressingValue::Immediate(byte),
+            AddressingModeType::Absolute => AddressingValue::Absolute(word()),
+            AddressingModeType::ZeroPage => AddressingValue::ZeroPage(byte),
+            AddressingModeType::Relative => AddressingValue::Relative(byte),
+            AddressingModeType::AbsoluteIndirect => AddressingValue::AbsoluteIndirect(word()),
+            AddressingModeType::AbsoluteIndexedWithX => AddressingValue::AbsoluteIndexedWithX(word()),
+            AddressingModeType::AbsoluteIndexedWithY => AddressingValue::AbsoluteIndexedWithY(word()),
+            AddressingModeType::ZeroPageIndexedWithX => AddressingValue::ZeroPageIndexedWithX(byte),
+            AddressingModeType::ZeroPageIndexedWithY => AddressingValue::ZeroPageIndexedWithY(byte),
+            AddressingModeType::IndexedIndirect => AddressingValue::IndexedIndirect(byte),
+            AddressingModeType::IndirectIndexed => AddressingValue::IndirectIndexed(byte),
+            AddressingModeType::ZeroPageIndirect => AddressingValue::ZeroPageIndirect(byte),
+            AddressingModeType::AbsoluteIndexedIndirect => {
+                AddressingValue::AbsoluteIndexedIndirect(word())
+            }
+            AddressingModeType::ZeroPageRelative => {
+                let offset = get_value_from_absolute(address.wrapping_add(1), bus);
+                AddressingValue::ZeroPageRelative(byte, offset)
+            }
+        }
+    }
+}
+
+impl AddressingValue {
+    pub fn get_value(
+        &self,
+        registers: &Registers,
+        bus: &mut dyn Bus
+    ) -> Option<Byte> {
+        match self {
+            AddressingValue::Accumulator => Some(registers.a),
+            AddressingValue::Implied => None,
+            AddressingValue::Immediate(value) => Some(*value),
+            AddressingValue::Absolute(value) => {
+                Some(get_value_from_absolute(*value, bus))
+            },
+            AddressingValue::ZeroPage(value) => {
+                Some(get_value_from_zero_page(*value, bus))
+            },
+            AddressingValue::Relative(value) => {
+                Some(*value)
+            },
+            AddressingValue::AbsoluteIndirect(value) => {
+                Some(get_value_from_absolute_indirect(*value, bus))
+            },
+            AddressingValue::AbsoluteIndexedWithX(value) => {
+                Some(get_value_from_absolute_indexed_with_register(*value, registers.x, bus))
+            }
+            AddressingValue::AbsoluteIndexedWithY(value) => {
+                Some(get_value_from_absolute_indexed_with_register(*value, registers.y, bus))
+            },
+            AddressingValue::ZeroPageIndexedWithX(value) => {
+                Some(get_value_from_zero_page_indexed_with_register(*value, registers.x, bus))
+            },
+            AddressingValue::ZeroPageIndexedWithY(value) => {
+                Some(get_value_from_zero_page_indexed_with_register(*value, registers.y, bus))
+            }
+            AddressingValue::IndexedIndirect(value) => {
+                Some(get_value_from_indexed_indirect(*value, registers, bus))
+            }
+            AddressingValue::IndirectIndexed(value) => {
+                Some(get_value_from_indirect_indexed(*value, registers, bus))
+            }
+            AddressingValue::ZeroPageIndirect(value) => {
+                Some(get_value_from_absolute(get_pointer_from_zero_page(*value, bus), bus))
+            }
+            AddressingValue::AbsoluteIndexedIndirect(value) => {
+                let address = get_address_from_absolute_indexed_indirect(*value, registers, bus);
+                Some(get_value_from_absolute(address, bus))
+            }
+            AddressingValue::ZeroPageRelative(value, _) => {
+                Some(get_value_from_zero_page(*value, bus))
+            }
+        }
+    }
+
+    /// Effective address of the memory location the operand refers to
+    ///
+    /// Relative operands resolve to the branch target from the current program counter.
+    /// Modes that do not reference memory return `None`.
+    pub fn get_effective_address(
+        &self,
+        registers: &Registers,
+        bus: &mut dyn Bus
+    ) -> Option<EffectiveAddress> {
+        let (address, page_crossed) = match self {
+            AddressingValue::Accumulator
+            | AddressingValue::Implied
+            | AddressingValue::Immediate(_) => return None,
+            AddressingValue::Absolute(value) => (*value, false),
+            AddressingValue::ZeroPage(value) => (*value as Byte2, false),
+            AddressingValue::Relative(value) => get_address_from_relative(*value, registers),
+            AddressingValue::AbsoluteIndirect(value) => {
+                (get_address_from_absolute_indirect(*value, bus), false)
+            }
+            AddressingValue::AbsoluteIndexedWithX(value) => {
+                get_address_from_absolute_indexed_with_register(*value, registers.x)
+            }
+            AddressingValue::AbsoluteIndexedWithY(value) => {
+                get_address_from_absolute_indexed_with_register(*value, registers.y)
+            }
+            AddressingValue::ZeroPageIndexedWithX(value) => {
+                (get_address_from_zero_page_indexed_with_register(*value, registers.x), false)
+            }
+            AddressingValue::ZeroPageIndexedWithY(value) => {
+                (get_address_from_zero_page_indexed_with_register(*value, registers.y), false)
+            }
+            AddressingValue::IndexedIndirect(value) => {
+                (get_address_from_indexed_indirect(*value, registers, bus), false)
+            }
+            AddressingValue::IndirectIndexed(value) => {
+                get_address_from_indirect_indexed(*value, registers, bus)
+            }
+            AddressingValue::ZeroPageIndirect(value) => {
+                (get_pointer_from_zero_page(*value, bus), false)
+            }
+            AddressingValue::AbsoluteIndexedIndirect(value) => {
+                (get_address_from_absolute_indexed_indirect(*value, registers, bus), false)
+            }
+            AddressingValue::ZeroPageRelative(value, _) => (*value as Byte2, false),
+        };
+        Some(EffectiveAddress { address, page_crossed })
+    }
+
+    /// Writes a value to the accumulator or to the effective address
+    ///
+    /// Returns `None` for modes that cannot be written to.
+    pub fn set_value(
+        &self,
+        value: Byte,
+        registers: &mut Registers,
+        bus: &mut dyn Bus
+    ) -> Option<()> {
+        match self {
+            AddressingValue::Accumulator => registers.a = value,
+            AddressingValue::Implied
+            | AddressingValue::Immediate(_)
+            | AddressingValue::Relative(_) => return None,
+            _ => {
+                let effective_address = self.get_effective_address(registers, bus)?;
+                set_value_to_absolute(effective_address.address, value, bus);
+            }
+        }
+        Some(())
+    }
+}
+
+/// Resolved operand address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveAddress {
+    pub address: Byte2,
+    /// Indexing or branching carried into a different page than the base address
+    pub page_crossed: bool,
+}
+
+/// Adds an index to a base address, reporting whether the page changed
+fn index_address(base: Byte2, register_contents: Byte) -> (Byte2, bool) {
+    let address = base.wrapping_add(register_contents as Byte2);
+    (address, base & 0xff00 != address & 0xff00)
+}
+
+fn get_address_from_relative(value: Byte, registers: &Registers) -> (Byte2, bool) {
+    let target = registers.p.wrapping_add(value as i8 as Byte2);
+    (target, registers.p & 0xff00 != target & 0xff00)
+}
+
+fn get_address_from_indirect_indexed(value: Byte, registers: &Registers, bus: &mut dyn Bus) -> (Byte2, bool) {
+    index_address(get_pointer_from_zero_page(value, bus), registers.y)
+}
+
+fn get_value_from_indirect_indexed(value: Byte, registers: &Registers, bus: &mut dyn Bus) -> Byte {
+    get_value_from_absolute(get_address_from_indirect_indexed(value, registers, bus).0, bus)
+}
+
+fn get_address_from_indexed_indirect(value: Byte, registers: &Registers, bus: &mut dyn Bus) -> Byte2 {
+    // The index is added without carry so the pointer stays in the zero page
+    get_pointer_from_zero_page(value.wrapping_add(registers.x), bus)
+}
+
+fn get_value_from_indexed_indirect(value: Byte, registers: &Registers, bus: &mut dyn Bus) -> Byte {
+    get_value_from_absolute(get_address_from_indexed_indirect(value, registers, bus), bus)
+}
+
+fn get_address_from_zero_page_indexed_with_register(value: Byte, register_contents: Byte) -> Byte2 {
+    // The index is added without carry so the address stays in the zero page
+    value.wrapping_add(register_contents) as Byte2
+}
+
+fn get_value_from_zero_page_indexed_with_register(value: Byte, register_contents: Byte, bus: &mut dyn Bus) -> Byte {
+    get_value_from_absolute(get_address_from_zero_page_indexed_with_register(value, register_contents), bus)
+}
+
+fn get_address_from_absolute_indexed_with_register(value: Byte2, register_contents: Byte) -> (Byte2, bool) {
+    index_address(value, register_contents)
+}
+
+fn get_value_from_absolute_indexed_with_register(value: Byte2, register_contents: Byte, bus: &mut dyn Bus) -> Byte {
+    get_value_from_absolute(get_address_from_absolute_indexed_with_register(value, register_contents).0, bus)
+}
+
+fn get_address_from_absolute_indirect(value: Byte2, bus: &mut dyn Bus) -> Byte2 {
+    // The NMOS 6502 does not carry into the high byte when fetching the pointer, so JMP ($xxFF)
+    // reads its high byte from $xx00 instead of the next page
+    let target_low = get_value_from_absolute(value, bus);
+    let target_high = get_value_from_absolute((value & 0xff00) | (value.wrapping_add(1) & 0x00ff), bus);
+    ((target_high as Byte2) << 8) | target_low as Byte2
+}
+
+fn get_address_from_absolute_indexed_indirect(value: Byte2, registers: &Registers, bus: &mut dyn Bus) -> Byte2 {
+    // Unlike JMP ($xxFF) on the NMOS 6502 the pointer fetch carries into the next page
+    let pointer = value.wrapping_add(registers.x as Byte2);
+    let target_low = get_value_from_absolute(pointer, bus);
+    let target_high = get_value_from_absolute(pointer.wrapping_add(1), bus);
+    ((target_high as Byte2) << 8) | target_low as Byte2
+}
+
+fn get_value_from_absolute_indirect(value: Byte2, bus: &mut dyn Bus) -> Byte {
+    get_value_from_absolute(get_address_from_absolute_indirect(value, bus), bus)
+}
+
+/// Reads a 16-bit pointer from the zero page, wrapping from $FF to $00 for the high byte
+fn get_pointer_from_zero_page(value: Byte, bus: &mut dyn Bus) -> Byte2 {
+    let target_low = get_value_from_zero_page(value, bus);
+    let target_high = get_value_from_zero_page(value.wrapping_add(1), bus);
+    ((target_high as Byte2) << 8) | target_low as Byte2
+}
+
+fn get_value_from_zero_page(value: Byte, bus: &mut dyn Bus) -> Byte {
+    bus.read(value as Byte2)
+}
+
+fn get_value_from_absolute(
+    value: Byte2,
+    bus: &mut dyn Bus
+) -> Byte {
+    bus.read(value)
+}
+
+fn set_value_to_absolute(
+    address: Byte2,
+    value: Byte,
+    bus: &mut dyn Bus
+) {
+    bus.write(address, value);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    /// Add memory to accumulator with carry
+    ADC,
+    /// And memory with accumulator
+    AND,
+    /// Arithmetic shift left one bit
+    ASL,
+    /// Compare memory and accumulator
+    CMP,
+    /// Decrement memory by one
+    DEC,
+    /// Exclusive or memory with accumulator
+    EOR,
+    /// Increment memory by one
+    INC,
+    /// Load accumulator with memory
+    LDA,
+    /// Load index X with memory
+    LDX,
+    /// Logical shift right one bit
+    LSR,
+    /// Or memory with accumulator
+    ORA,
+    /// Rotate left one bit
+    ROL,
+    /// Rotate right one bit
+    ROR,
+    /// Subtract memory from accumulator with borrow
+    SBC,
+    /// Store accumulator in memory
+    STA,
+    /// Store index X in memory
+    STX,
+
+    BIT,
+    CPX,
+    CPY,
+    JMP,
+    #[allow(non_camel_case_types)]
+    JMP_ABS,
+    LDY,
+    STY,
+
+    // Conditional Branches
+    /// Branch on result plus
+    BPL,
+    /// Branch on carry clear
+    BCC,
+    /// Branch on carry set
+    BCS,
+    /// Branch on result zero
+    BEQ,
+    /// Branch on result minus
+    BMI,
+    /// Branch on result not zero
+    BNE,
+    /// Branch on overflow clear
+    BVC,
+    /// Branch on overflow set
+    BVS,
+
+    /// Break
+    BRK,
+    /// Clear carry
+    CLC,
+    /// Clear decimal
+    CLD,
+    /// Clear interrupt
+    CLI,
+    /// Clear overflow
+    CLV,
+    /// Decrement X
+    DEX,
+    /// Decrement Y
+    DEY,
+    /// Increment X
+    INX,
+    /// Increment Y
+    INY,
+    /// Shuould this just be JSR with abs?
+    JSR,
+    /// No operation
+    NOP,
+    /// Push accumulator
+    PHA,
+    /// Push processor status
+    PHP,
+    /// Pull accumulator
+    PLA,
+    /// Pull processor status
+    PLP,
+    /// Return from interrupt
+    RTI,
+    /// Return from subroutine
+    RTS,
+    /// Set carry
+    SEC,
+    /// Set decimal
+    SED,
+    /// Set interrupt
+    SEI,
+    /// Transfer A to X
+    TAX,
+    /// Transfer A to Y
+    TAY,
+    /// Transfer stack pointer to X
+    TSX,
+    /// Transfer X to A
+    TXA,
+    /// Transfer X to stack pointer
+    TXS,
+    /// Transfer Y to A
+    TYA,
+
+    // Undocumented NMOS instructions
+    /// Shift left memory then or with accumulator
+    SLO,
+    /// Rotate left memory then and with accumulator
+    RLA,
+    /// Shift right memory then exclusive or with accumulator
+    SRE,
+    /// Rotate right memory then add to accumulator with carry
+    RRA,
+    /// Store accumulator and index X
+    SAX,
+    /// Load accumulator and index X with memory
+    LAX,
+    /// Decrement memory then compare with accumulator
+    DCP,
+    /// Increment memory then subtract from accumulator with borrow
+    ISC,
+    /// And immediate with accumulator, copying the negative flag into carry
+    ANC,
+    /// And immediate with accumulator then shift right
+    ALR,
+    /// And immediate with accumulator then rotate right
+    ARR,
+    /// Unstable: and index X and immediate into accumulator
+    ANE,
+    /// Unstable: and immediate into accumulator and index X
+    LXA,
+    /// Subtract immediate from accumulator and index X into index X, also known as SBX
+    AXS,
+    /// Unstable: store accumulator and index X and high address byte
+    SHA,
+    /// Unstable: store index X and high address byte
+    SHX,
+    /// Unstable: store index Y and high address byte
+    SHY,
+    /// Unstable: transfer accumulator and index X to stack pointer, then store like SHA
+    TAS,
+    /// And memory with stack pointer into accumulator, index X and stack pointer
+    LAS,
+    /// Halt the processor, also known as KIL
+    JAM,
+
+    // WDC 65C02 instructions
+    /// Branch Always
+    BRA,
+    /// Push X Register
+    PHX,
+    /// Push Y Register
+    PHY,
+    /// Pull X Register
+    PLX,
+    /// Pull Y Register
+    PLY,
+    /// Store Zero
+    STZ,
+    /// Test and Reset Bits
+    TRB,
+    /// Test and Set Bits
+    TSB,
+    /// Reset Memory Bit
+    RMB(Byte),
+    /// Set Memory Bit
+    SMB(Byte),
+    /// Branch on Bit Reset
+    BBR(Byte),
+    /// Branch on Bit Set
+    BBS(Byte),
+    /// Wait for Interrupt
+    WAI,
+    /// Stop the Processor
+    STP,
+}
+
+impl Opcode {
+    /// Whether the instruction only exists as an undocumented NMOS opcode
+    ///
+    /// The undocumented NOP slots and SBC $EB reuse documented instructions, see
+    /// [`instructions::parser::is_undocumented_opcode`] for checking an opcode byte.
+    pub fn is_undocumented(&self) -> bool {
+        matches!(
+            self,
+            Opcode::SLO
+                | Opcode::RLA
+                | Opcode::SRE
+                | Opcode::RRA
+                | Opcode::SAX
+                | Opcode::LAX
+                | Opcode::DCP
+                | Opcode::ISC
+                | Opcode::ANC
+                | Opcode::ALR
+                | Opcode::ARR
+                | Opcode::ANE
+                | Opcode::LXA
+                | Opcode::AXS
+                | Opcode::SHA
+                | Opcode::SHX
+                | Opcode::SHY
+                | Opcode::TAS
+                | Opcode::LAS
+                | Opcode::JAM
+        )
+    }
+
+    /// Assembler mnemonic, with the bit number appended for the 65C02 bit instructions
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::ADC => "ADC",
+            Opcode::AND => "AND",
+            Opcode::ASL => "ASL",
+            Opcode::CMP => "CMP",
+            Opcode::DEC => "DEC",
+            Opcode::EOR => "EOR",
+            Opcode::INC => "INC",
+            Opcode::LDA => "LDA",
+            Opcode::LDX => "LDX",
+            Opcode::LSR => "LSR",
+            Opcode::ORA => "ORA",
+            Opcode::ROL => "ROL",
+            Opcode::ROR => "ROR",
+            Opcode::SBC => "SBC",
+            Opcode::STA => "STA",
+            Opcode::STX => "STX",
+            Opcode::BIT => "BIT",
+            Opcode::CPX => "CPX",
+            Opcode::CPY => "CPY",
+            Opcode::LDY => "LDY",
+            Opcode::STY => "STY",
+            Opcode::BPL => "BPL",
+            Opcode::BCC => "BCC",
+            Opcode::BCS => "BCS",
+            Opcode::BEQ => "BEQ",
+            Opcode::BMI => "BMI",
+            Opcode::BNE => "BNE",
+            Opcode::BVC => "BVC",
+            Opcode::BVS => "BVS",
+            Opcode::BRK => "BRK",
+            Opcode::CLC => "CLC",
+            Opcode::CLD => "CLD",
+            Opcode::CLI => "CLI",
+            Opcode::CLV => "CLV",
+            Opcode::DEX => "DEX",
+            Opcode::DEY => "DEY",
+            Opcode::INX => "INX",
+            Opcode::INY => "INY",
+            Opcode::JMP | Opcode::JMP_ABS => "JMP",
+            Opcode::JSR => "JSR",
+            Opcode::NOP => "NOP",
+            Opcode::PHA => "PHA",
+            Opcode::PHP => "PHP",
+            Opcode::PLA => "PLA",
+            Opcode::PLP => "PLP",
+            Opcode::RTI => "RTI",
+            Opcode::RTS => "RTS",
+            Opcode::SEC => "SEC",
+            Opcode::SED => "SED",
+            Opcode::SEI => "SEI",
+            Opcode::TAX => "TAX",
+            Opcode::TAY => "TAY",
+            Opcode::TSX => "TSX",
+            Opcode::TXA => "TXA",
+            Opcode::TXS => "TXS",
+            Opcode::TYA => "TYA",
+            Opcode::SLO => "SLO",
+            Opcode::RLA => "RLA",
+            Opcode::SRE => "SRE",
+            Opcode::RRA => "RRA",
+            Opcode::SAX => "SAX",
+            Opcode::LAX => "LAX",
+            Opcode::DCP => "DCP",
+            Opcode::ISC => "ISC",
+            Opcode::ANC => "ANC",
+            Opcode::ALR => "ALR",
+            Opcode::ARR => "ARR",
+            Opcode::ANE => "ANE",
+            Opcode::LXA => "LXA",
+            Opcode::AXS => "AXS",
+            Opcode::SHA => "SHA",
+            Opcode::SHX => "SHX",
+            Opcode::SHY => "SHY",
+            Opcode::TAS => "TAS",
+            Opcode::LAS => "LAS",
+            Opcode::JAM => "JAM",
+            Opcode::BRA => "BRA",
+            Opcode::PHX => "PHX",
+            Opcode::PHY => "PHY",
+            Opcode::PLX => "PLX",
+            Opcode::PLY => "PLY",
+            Opcode::STZ => "STZ",
+            Opcode::TRB => "TRB",
+            Opcode::TSB => "TSB",
+            Opcode::WAI => "WAI",
+            Opcode::STP => "STP",
+            Opcode::RMB(bit) => ["RMB0", "RMB1", "RMB2", "RMB3", "RMB4", "RMB5", "RMB6", "RMB7"][*bit as usize],
+            Opcode::SMB(bit) => ["SMB0", "SMB1", "SMB2", "SMB3", "SMB4", "SMB5", "SMB6", "SMB7"][*bit as usize],
+            Opcode::BBR(bit) => ["BBR0", "BBR1", "BBR2", "BBR3", "BBR4", "BBR5", "BBR6", "BBR7"][*bit as usize],
+            Opcode::BBS(bit) => ["BBS0", "BBS1", "BBS2", "BBS3", "BBS4", "BBS5", "BBS6", "BBS7"][*bit as usize],
+        }
+    }
+}
+
+/// Reads and decodes the instruction at the program counter
+pub fn read_opcode(
+    variant: CpuVariant,
+    registers: &Registers,
+    bus: &mut dyn Bus,
+) -> Result<Instruction, DecodeError> {
+    let address = registers.p;
+    let opcode = get_value_from_absolute(address, bus);
+    let entry = dispatch_table(variant)[opcode as usize]
+        .as_ref()
+        .ok_or(DecodeError::UnknownOpcode { opcode, address })?;
+    let addressing_value =
+        AddressingModeBuilder::new(entry.addressing_mode).build(address.wrapping_add(1), bus);
+    Ok(Instruction {
+        opcode: entry.opcode,
+        addressing_value,
+        length: entry.length,
+        cycles: entry.cycles,
+        handler: entry.handler,
+        decimal_handler: entry.decimal_handler,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Memory;
+    use crate::cpu::Cpu;
+
+    /// Effective address and page crossing of an operand, `None` when it has no address
+    fn resolve(operand: AddressingValue, registers: &Registers, bus: &mut Memory) -> Option<(Byte2, bool)> {
+        let effective_address = operand.get_effective_address(registers, bus)?;
+        Some((effective_address.address, effective_address.page_crossed))
+    }
+
+    #[test]
+    fn effective_addresses_report_page_crossings() {
+        let mut bus = Memory::new();
+        bus.load(0x0010, &[0xff, 0x20]);
+        let mut registers = Registers::new(0x0610);
+        registers.x = 1;
+        registers.y = 1;
+        let mut resolve = |addressing_value| resolve(addressing_value, &registers, &mut bus);
+        assert_eq!(resolve(AddressingValue::AbsoluteIndexedWithX(0x10ff)), Some((0x1100, true)));
+        assert_eq!(resolve(AddressingValue::AbsoluteIndexedWithY(0x10fe)), Some((0x10ff, false)));
+        assert_eq!(resolve(AddressingValue::IndirectIndexed(0x10)), Some((0x2100, true)));
+        assert_eq!(resolve(AddressingValue::IndexedIndirect(0x0f)), Some((0x20ff, false)));
+        // Branches cross from the page of the next instruction
+        assert_eq!(resolve(AddressingValue::Relative(0xe0)), Some((0x05f0, true)));
+        assert_eq!(resolve(AddressingValue::Relative(0x10)), Some((0x0620, false)));
+        assert_eq!(resolve(AddressingValue::Immediate(0x10)), None);
+        assert_eq!(resolve(AddressingValue::Accumulator), None);
+    }
+
+    #[test]
+    fn set_value_writes_the_accumulator_or_memory() {
+        let mut bus = Memory::new();
+        let mut registers = Registers::new(0);
+        let mut set_value = |addressing_value: AddressingValue, value, registers: &mut Registers| {
+            addressing_value.set_value(value, registers, &mut bus)
+        };
+        assert_eq!(set_value(AddressingValue::Accumulator, 0x42, &mut registers), Some(()));
+        assert_eq!(set_value(AddressingValue::ZeroPage(0x10), 0x24, &mut registers), Some(()));
+        registers.x = 2;
+        assert_eq!(set_value(AddressingValue::AbsoluteIndexedWithX(0x1234), 0x99, &mut registers), Some(()));
+        assert_eq!(set_value(AddressingValue::Immediate(0x10), 0x11, &mut registers), None);
+        assert_eq!(set_value(AddressingValue::Implied, 0x11, &mut registers), None);
+        assert_eq!(registers.a, 0x42);
+        assert_eq!((bus.peek(0x0000), bus.peek(0x0010), bus.peek(0x1236)), (0x00, 0x24, 0x99));
+    }
+
+    /// Runs one instruction at $0600 with X and Y set to 1, zero page bytes $FF and $00 forming
+    /// the pointer $1234 and $0100 holding the $56 a carried high byte would come from
+    fn run(variant: CpuVariant, instruction: &[Byte]) -> Cpu {
+        let mut memory = Memory::new();
+        memory.load(0x0000, &[0x12]);
+        memory.load(0x00ff, &[0x34, 0x56]);
+        memory.load(0x0600, instruction);
+        memory.load(0x1234, &[0x77]);
+        memory.load(0x1235, &[0x78]);
+        memory.load(0x5634, &[0x88]);
+        let mut cpu = Cpu::new(memory, 0x0600);
+        cpu.set_variant(variant);
+        cpu.registers_mut().x = 1;
+        cpu.registers_mut().y = 1;
+        cpu.step().unwrap();
+        cpu
+    }
+
+    #[test]
+    fn zero_page_indexing_wraps_within_the_zero_page() {
+        // LDA $FF,X
+        assert_eq!(run(CpuVariant::Nmos6502, &[0xb5, 0xff]).registers().a, 0x12);
+    }
+
+    #[test]
+    fn indexed_indirect_pointer_wraps_within_the_zero_page() {
+        // LDA ($FE,X) fetches the pointer from $FF and $00
+        assert_eq!(run(CpuVariant::Nmos6502, &[0xa1, 0xfe]).registers().a, 0x77);
+    }
+
+    #[test]
+    fn indirect_indexed_pointer_wraps_within_the_zero_page() {
+        // LDA ($FF),Y reads $1234 + 1
+        assert_eq!(run(CpuVariant::Nmos6502, &[0xb1, 0xff]).registers().a, 0x78);
+    }
+
+    #[test]
+    fn jmp_indirect_pointer_wraps_within_its_page_on_nmos_only() {
+        let jmp = [0x6c, 0xff, 0x10];
+        let mut memory = Memory::new();
+        memory.load(0x0600, &jmp);
+        memory.load(0x1000, &[0x02]);
+        memory.load(0x10ff, &[0x00, 0x03]);
+        for (variant, target) in [(CpuVariant::Nmos6502, 0x0200), (CpuVariant::Wdc65C02, 0x0300)] {
+            let mut cpu = Cpu::new(memory.clone(), 0x0600);
+            cpu.set_variant(variant);
+            cpu.step().unwrap();
+            assert_eq!(cpu.registers().p, target, "{variant:?}");
+        }
+    }
+
+    #[test]
+    fn absolute_indexing_wraps_to_the_zero_page() {
+        // LDA $FFFF,X
+        let cpu = run(CpuVariant::Nmos6502, &[0xbd, 0xff, 0xff]);
+        assert_eq!(cpu.registers().a, 0x12);
+        assert_eq!(cpu.cycles(), 5);
+    }
+
+    #[test]
+    fn status_flags_round_trip_with_bit_5_set() {
+        for value in 0..=Byte::MAX {
+            assert_eq!(Byte::from(&StatusFlags::from(value)), value | 0x20, "{value:08b}");
+        }
+        assert_eq!(Byte::from(&StatusFlags::new()), 0x20);
+    }
+
+    #[test]
+    fn status_flags_display_as_nv_bdizc() {
+        assert_eq!(StatusFlags::from(0b1100_0011).to_string(), "NV-bdiZC");
+        assert_eq!(StatusFlags::from(0b0001_1100).to_string(), "nv-BDIzc");
+        assert_eq!(StatusFlags::new().to_string(), "nv-bdizc");
+    }
+}
@@ -0,0 +1,357 @@
+use crate::bus::Bus;
+use crate::nes::cartridge::Cartridge;
+use crate::nes::open_bus::{ppu_latch_decay_cycles, DecayingLatch, Driven};
+use crate::nes::TvSystem;
+use crate::power::RamFill;
+use crate::{Byte, Byte2};
+
+/// Size of the internal RAM, mirrored four times below $2000
+const RAM_SIZE: Byte2 = 0x800;
+/// The eight PPU registers repeat every 8 bytes from $2000 to $3FFF
+const PPU_REGISTERS: Byte2 = 0x2000;
+const PPU_REGISTER_COUNT: Byte2 = 8;
+
+/// Device answering reads and writes in one window of the CPU address space
+///
+/// Addresses are passed with mirroring already removed. Bits a device does not drive on a read
+/// float and read as open bus.
+pub trait CpuDevice {
+    fn read(&mut self, address: Byte2) -> Driven;
+
+    fn write(&mut self, address: Byte2, value: Byte);
+
+    /// Value a read would return, without its side effects
+    fn peek(&self, address: Byte2) -> Driven;
+}
+
+/// Part of the NES CPU address space an address decodes to, with mirroring removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Internal RAM at $0000-$07FF, mirrored up to $1FFF
+    Ram(Byte2),
+    /// PPU register at $2000-$2007, mirrored up to $3FFF
+    Ppu(Byte2),
+    /// APU and I/O registers at $4000-$4017
+    ApuIo(Byte2),
+    /// CPU test mode registers at $4018-$401F, disabled on retail consoles
+    TestMode(Byte2),
+    /// Cartridge space from $4020 up
+    Cartridge(Byte2),
+}
+
+/// Decodes a CPU address the way the NES address decoder does
+pub fn decode_address(address: Byte2) -> Region {
+    match address {
+        0x0000..=0x1fff => Region::Ram(address % RAM_SIZE),
+        0x2000..=0x3fff => Region::Ppu(PPU_REGISTERS + address % PPU_REGISTER_COUNT),
+        0x4000..=0x4017 => Region::ApuIo(address),
+        0x4018..=0x401f => Region::TestMode(address),
+        _ => Region::Cartridge(address),
+    }
+}
+
+/// CPU address space of the NES: internal RAM plus the PPU, the APU and I/O registers, the test
+/// mode registers and the cartridge plugged into their windows
+///
+/// Bits nothing drives read back the last value seen on the CPU data bus, except in the PPU
+/// window where the PPU answers every read from its own decaying I/O latch.
+pub struct NesBus {
+    ram: [Byte; RAM_SIZE as usize],
+    ppu: Option<Box<dyn CpuDevice>>,
+    apu_io: Option<Box<dyn CpuDevice>>,
+    test_mode: Option<Box<dyn CpuDevice>>,
+    cartridge: Option<Cartridge>,
+    /// Last value driven onto the CPU data bus
+    open_bus: Byte,
+    /// I/O data bus between the CPU and the PPU registers
+    ppu_latch: DecayingLatch,
+    ppu_latch_decay: bool,
+    tv_system: TvSystem,
+    /// CPU cycles counted by [`Bus::tick`], the clock of the PPU latch decay
+    cycles: u64,
+}
+
+impl NesBus {
+    /// Creates a bus with zeroed RAM and nothing plugged in
+    pub fn new() -> Self {
+        NesBus {
+            ram: [0; RAM_SIZE as usize],
+            ppu: None,
+            apu_io: None,
+            test_mode: None,
+            cartridge: None,
+            open_bus: 0,
+            ppu_latch: DecayingLatch::new(Some(ppu_latch_decay_cycles(TvSystem::default()))),
+            ppu_latch_decay: true,
+            tv_system: TvSystem::default(),
+            cycles: 0,
+        }
+    }
+
+    /// Creates a bus with RAM in the given power-on state and nothing plugged in
+    pub fn with_ram_fill(fill: RamFill) -> Self {
+        let mut bus = NesBus::new();
+        fill.fill(&mut bus.ram);
+        bus
+    }
+
+    pub fn ram(&self) -> &[Byte] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [Byte] {
+        &mut self.ram
+    }
+
+    pub fn set_ppu(&mut self, ppu: Option<Box<dyn CpuDevice>>) {
+        self.ppu = ppu;
+    }
+
+    pub fn set_apu_io(&mut self, apu_io: Option<Box<dyn CpuDevice>>) {
+        self.apu_io = apu_io;
+    }
+
+    pub fn set_test_mode(&mut self, test_mode: Option<Box<dyn CpuDevice>>) {
+        self.test_mode = test_mode;
+    }
+
+    /// Plugs in a cartridge and switches to the TV system its header asks for
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.set_tv_system(cartridge.header.timing.tv_system());
+        self.cartridge = Some(cartridge);
+    }
+
+    pub fn remove_cartridge(&mut self) -> Option<Cartridge> {
+        self.cartridge.take()
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    pub fn tv_system(&self) -> TvSystem {
+        self.tv_system
+    }
+
+    /// Sets the console region, whose clock rate times the PPU latch decay
+    pub fn set_tv_system(&mut self, tv_system: TvSystem) {
+        self.tv_system = tv_system;
+        self.set_ppu_latch_decay(self.ppu_latch_decay);
+    }
+
+    /// Value the floating bits of a read outside the PPU window return
+    pub fn open_bus(&self) -> Byte {
+        self.open_bus
+    }
+
+    /// Value the floating bits of a PPU register read return
+    pub fn ppu_latch(&self) -> Byte {
+        self.ppu_latch.value(self.cycles)
+    }
+
+    /// Enables or disables the decay of the PPU I/O latch, disabled it holds its bits forever
+    /// which keeps tests independent of timing
+    pub fn set_ppu_latch_decay(&mut self, enabled: bool) {
+        self.ppu_latch_decay = enabled;
+        self.ppu_latch.set_decay(enabled.then(|| ppu_latch_decay_cycles(self.tv_system)));
+    }
+
+    /// Device plugged into the window of a region other than RAM, with the address to pass it
+    fn device(&self, region: Region) -> Option<(&dyn CpuDevice, Byte2)> {
+        let (device, address) = match region {
+            Region::Ram(_) => return None,
+            Region::Ppu(address) => (self.ppu.as_deref(), address),
+            Region::ApuIo(address) => (self.apu_io.as_deref(), address),
+            Region::TestMode(address) => (self.test_mode.as_deref(), address),
+            Region::Cartridge(address) => {
+                (self.cartridge.as_ref().map(|cartridge| cartridge as &dyn CpuDevice), address)
+            }
+        };
+        Some((device?, address))
+    }
+
+    fn device_mut(&mut self, region: Region) -> Option<(&mut dyn CpuDevice, Byte2)> {
+        let (device, address) = match region {
+            Region::Ram(_) => return None,
+            Region::Ppu(address) => (self.ppu.as_deref_mut(), address),
+            Region::ApuIo(address) => (self.apu_io.as_deref_mut(), address),
+            Region::TestMode(address) => (self.test_mode.as_deref_mut(), address),
+            Region::Cartridge(address) => {
+                (self.cartridge.as_mut().map(|cartridge| cartridge as &mut dyn CpuDevice), address)
+            }
+        };
+        Some((device?, address))
+    }
+}
+
+impl Default for NesBus {
+    fn default() -> Self {
+        NesBus::new()
+    }
+}
+
+impl Bus for NesBus {
+    fn read(&mut self, address: Byte2) -> Byte {
+        let region = decode_address(address);
+        let driven = match region {
+            Region::Ram(offset) => Driven::full(self.ram[offset as usize]),
+            region => self
+                .device_mut(region)
+                .map_or(Driven::NONE, |(device, address)| device.read(address)),
+        };
+        self.open_bus = match region {
+            Region::Ppu(_) => {
+                let value = driven.resolve(self.ppu_latch.value(self.cycles));
+                self.ppu_latch.drive(driven, self.cycles);
+                value
+            }
+            _ => driven.resolve(self.open_bus),
+        };
+        self.open_bus
+    }
+
+    fn write(&mut self, address: Byte2, value: Byte) {
+        self.open_bus = value;
+        let region = decode_address(address);
+        match region {
+            Region::Ram(offset) => self.ram[offset as usize] = value,
+            region => {
+                if let Region::Ppu(_) = region {
+                    self.ppu_latch.drive(Driven::full(value), self.cycles);
+                }
+                if let Some((device, address)) = self.device_mut(region) {
+                    device.write(address, value);
+                }
+            }
+        }
+    }
+
+    fn peek(&self, address: Byte2) -> Byte {
+        let region = decode_address(address);
+        let driven = match region {
+            Region::Ram(offset) => Driven::full(self.ram[offset as usize]),
+            region => self
+                .device(region)
+                .map_or(Driven::NONE, |(device, address)| device.peek(address)),
+        };
+        match region {
+            Region::Ppu(_) => driven.resolve(self.ppu_latch.value(self.cycles)),
+            _ => driven.resolve(self.open_bus),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.cycles += 1;
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.mapper_mut().cpu_cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.cartridge.as_ref().is_some_and(|cartridge| cartridge.mapper().irq())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Device driving only the low five bits, like the controller ports
+    struct LowBits;
+
+    impl CpuDevice for LowBits {
+        fn read(&mut self, address: Byte2) -> Driven {
+            self.peek(address)
+        }
+
+        fn write(&mut self, _address: Byte2, _value: Byte) {}
+
+        fn peek(&self, _address: Byte2) -> Driven {
+            Driven::partial(0x01, 0x1f)
+        }
+    }
+
+    #[test]
+    fn ram_mirrors_every_2_kib_below_2000() {
+        for address in [0x0000, 0x0800, 0x1000, 0x1800] {
+            assert_eq!(decode_address(address), Region::Ram(0x0000));
+            assert_eq!(decode_address(address + 0x07ff), Region::Ram(0x07ff));
+        }
+        let mut bus = NesBus::new();
+        bus.write(0x1abc, 0x42);
+        assert_eq!((bus.read(0x02bc), bus.ram()[0x02bc]), (0x42, 0x42));
+    }
+
+    #[test]
+    fn ppu_registers_mirror_every_8_bytes_up_to_3fff() {
+        assert_eq!(decode_address(0x2000), Region::Ppu(0x2000));
+        assert_eq!(decode_address(0x2008), Region::Ppu(0x2000));
+        assert_eq!(decode_address(0x3ffa), Region::Ppu(0x2002));
+        assert_eq!(decode_address(0x3fff), Region::Ppu(0x2007));
+    }
+
+    #[test]
+    fn decodes_apu_io_test_mode_and_cartridge_windows() {
+        assert_eq!(decode_address(0x4000), Region::ApuIo(0x4000));
+        assert_eq!(decode_address(0x4017), Region::ApuIo(0x4017));
+        assert_eq!(decode_address(0x4018), Region::TestMode(0x4018));
+        assert_eq!(decode_address(0x401f), Region::TestMode(0x401f));
+        assert_eq!(decode_address(0x4020), Region::Cartridge(0x4020));
+        assert_eq!(decode_address(0xffff), Region::Cartridge(0xffff));
+    }
+
+    #[test]
+    fn empty_cartridge_slot_reads_open_bus() {
+        let mut bus = NesBus::new();
+        bus.write(0x0000, 0x5a);
+        assert_eq!(bus.read(0x0000), 0x5a);
+        assert_eq!((bus.read(0x8000), bus.peek(0xfffc)), (0x5a, 0x5a));
+        bus.write(0x6000, 0xa5);
+        assert_eq!(bus.read(0x6000), 0xa5);
+    }
+
+    #[test]
+    fn unmapped_reads_return_the_last_value_on_the_bus() {
+        let mut bus = NesBus::new();
+        bus.write(0x0010, 0x42);
+        bus.read(0x0010);
+        assert_eq!(bus.read(0x5000), 0x42);
+        assert_eq!(bus.open_bus(), 0x42);
+    }
+
+    #[test]
+    fn partially_driven_reads_keep_the_undriven_bits() {
+        let mut bus = NesBus::new();
+        bus.set_apu_io(Some(Box::new(LowBits)));
+        bus.write(0x0010, 0x40);
+        bus.read(0x0010);
+        assert_eq!(bus.read(0x4016), 0x41);
+    }
+
+    #[test]
+    fn ppu_latch_decays_to_zero_after_its_deadline() {
+        let mut bus = NesBus::new();
+        bus.write(0x2000, 0xff);
+        for _ in 1..ppu_latch_decay_cycles(bus.tv_system()) {
+            bus.tick();
+        }
+        assert_eq!((bus.peek(0x2002), bus.ppu_latch()), (0xff, 0xff));
+        bus.tick();
+        assert_eq!((bus.read(0x2002), bus.ppu_latch()), (0x00, 0x00));
+    }
+
+    #[test]
+    fn ppu_latch_without_decay_never_fades() {
+        let mut bus = NesBus::new();
+        bus.set_ppu_latch_decay(false);
+        bus.write(0x2000, 0xff);
+        for _ in 0..ppu_latch_decay_cycles(bus.tv_system()) * 2 {
+            bus.tick();
+        }
+        assert_eq!(bus.read(0x2002), 0xff);
+    }
+}
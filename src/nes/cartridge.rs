@@ -0,0 +1,212 @@
+use crate::nes::bus::CpuDevice;
+use crate::nes::ines::{Header, InesError, HEADER_SIZE, TRAINER_SIZE};
+use crate::nes::mapper::{create_mapper, Mapper};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+/// Offset of the trainer in PRG-RAM, which starts at $6000 and receives it at $7000
+const TRAINER_OFFSET: usize = 0x1000;
+
+/// How the two nametables inside the console are arranged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    /// $2000 and $2400 share a nametable, as do $2800 and $2C00, for vertical scrolling
+    Horizontal,
+    /// $2000 and $2800 share a nametable, as do $2400 and $2C00, for horizontal scrolling
+    Vertical,
+    /// Every nametable address shows the first nametable
+    SingleScreenLower,
+    /// Every nametable address shows the second nametable
+    SingleScreenUpper,
+    /// The board brings its own RAM for four separate nametables
+    FourScreen,
+}
+
+impl Mirroring {
+    /// Which of the four nametables at $2000, $2400, $2800 and $2C00 a PPU address selects
+    /// after mirroring, 0 and 1 being the two inside the console
+    pub fn nametable(&self, address: Byte2) -> usize {
+        let nametable = (address as usize >> 10) & 0b11;
+        match self {
+            Mirroring::Horizontal => nametable >> 1,
+            Mirroring::Vertical => nametable & 1,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => nametable,
+        }
+    }
+}
+
+/// ROM and RAM chips on a cartridge board, which its mapper banks into the console buses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeMemory {
+    pub prg_rom: Vec<Byte>,
+    /// PRG-RAM at $6000-$7FFF, battery-backed or not, empty on boards without any
+    pub prg_ram: Vec<Byte>,
+    /// CHR-ROM, or CHR-RAM when `chr_is_ram`
+    pub chr: Vec<Byte>,
+    pub chr_is_ram: bool,
+}
+
+impl CartridgeMemory {
+    /// PRG-ROM byte at `offset`, mirrored across the chip size
+    pub fn read_prg_rom(&self, offset: usize) -> Byte {
+        self.prg_rom[offset % self.prg_rom.len()]
+    }
+
+    /// PRG-RAM byte at `offset`, mirrored across the RAM size, undriven without RAM
+    pub fn read_prg_ram(&self, offset: usize) -> Driven {
+        match self.prg_ram.is_empty() {
+            true => Driven::NONE,
+            false => Driven::full(self.prg_ram[offset % self.prg_ram.len()]),
+        }
+    }
+
+    pub fn write_prg_ram(&mut self, offset: usize, value: Byte) {
+        if !self.prg_ram.is_empty() {
+            let length = self.prg_ram.len();
+            self.prg_ram[offset % length] = value;
+        }
+    }
+
+    /// CHR byte at `offset`, mirrored across the chip size
+    pub fn read_chr(&self, offset: usize) -> Byte {
+        match self.chr.is_empty() {
+            true => 0,
+            false => self.chr[offset % self.chr.len()],
+        }
+    }
+
+    /// Writes CHR-RAM, writes to CHR-ROM are ignored
+    pub fn write_chr(&mut self, offset: usize, value: Byte) {
+        if self.chr_is_ram && !self.chr.is_empty() {
+            let length = self.chr.len();
+            self.chr[offset % length] = value;
+        }
+    }
+}
+
+/// Game cartridge: the header describing the board and the mapper driving it
+pub struct Cartridge {
+    pub header: Header,
+    mapper: Box<dyn Mapper>,
+}
+
+impl Cartridge {
+    /// Wraps a mapper built by hand, such as a board the loader does not know
+    pub fn new(header: Header, mapper: Box<dyn Mapper>) -> Self {
+        Cartridge { header, mapper }
+    }
+
+    /// Loads an iNES or NES 2.0 image, copying its trainer into PRG-RAM
+    pub fn from_ines(bytes: &[Byte]) -> Result<Cartridge, InesError> {
+        let header = Header::parse(bytes)?;
+        if header.prg_rom_size == 0 {
+            return Err(InesError::MissingPrgRom);
+        }
+        let image_size = header.image_size()?;
+        if bytes.len() < image_size {
+            return Err(InesError::TruncatedData {
+                expected: image_size,
+                length: bytes.len(),
+            });
+        }
+        let mut offset = HEADER_SIZE;
+        let mut take = |size: usize| {
+            let data = bytes[offset..offset + size].to_vec();
+            offset += size;
+            data
+        };
+        let trainer = match header.trainer {
+            true => take(TRAINER_SIZE),
+            false => Vec::new(),
+        };
+        let prg_rom = take(header.prg_rom_size);
+        let chr_rom = take(header.chr_rom_size);
+        let mut prg_ram = vec![0; header.prg_ram_size + header.prg_nvram_size];
+        if !trainer.is_empty() && !prg_ram.is_empty() {
+            let start = TRAINER_OFFSET % prg_ram.len();
+            let end = (start + TRAINER_SIZE).min(prg_ram.len());
+            prg_ram[start..end].copy_from_slice(&trainer[..end - start]);
+        }
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = match chr_is_ram {
+            true => vec![0; header.chr_ram_size + header.chr_nvram_size],
+            false => chr_rom,
+        };
+        let memory = CartridgeMemory {
+            prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+        };
+        let mapper = create_mapper(&header, memory)?;
+        Ok(Cartridge { header, mapper })
+    }
+
+    pub fn mapper(&self) -> &dyn Mapper {
+        self.mapper.as_ref()
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.mapper.as_mut()
+    }
+}
+
+impl CpuDevice for Cartridge {
+    fn read(&mut self, address: Byte2) -> Driven {
+        self.mapper.cpu_read(address)
+    }
+
+    fn write(&mut self, address: Byte2, value: Byte) {
+        self.mapper.cpu_write(address, value);
+    }
+
+    fn peek(&self, address: Byte2) -> Driven {
+        self.mapper.cpu_peek(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::ines::MAGIC;
+
+    /// NROM image with the given header fields in bytes 4 to 7 and `data` after the header
+    fn image(fields: [Byte; 4], data: &[Byte]) -> Vec<Byte> {
+        let mut image = MAGIC.to_vec();
+        image.extend(fields);
+        image.extend([0; 8]);
+        image.extend(data);
+        image
+    }
+
+    #[test]
+    fn rejects_images_without_prg_rom() {
+        assert_eq!(Cartridge::from_ines(&image([0, 1, 0, 0], &[0; 0x2000])).err(), Some(InesError::MissingPrgRom));
+    }
+
+    #[test]
+    fn rejects_images_shorter_than_their_header_announces() {
+        let error = Cartridge::from_ines(&image([1, 1, 0, 0], &[0; 0x4000])).err();
+        let expected = HEADER_SIZE + 0x6000;
+        assert_eq!(error, Some(InesError::TruncatedData { expected, length: HEADER_SIZE + 0x4000 }));
+    }
+
+    #[test]
+    fn rejects_nes2_sizes_adding_up_past_memory() {
+        let mut bytes = image([0xfc, 0xfc, 0, 0x08], &[]);
+        bytes[9] = 0xff;
+        assert_eq!(Cartridge::from_ines(&bytes).err(), Some(InesError::ImageSizeOverflow));
+    }
+
+    #[test]
+    fn copies_the_trainer_to_7000() {
+        let mut data = vec![0xaa; TRAINER_SIZE];
+        data.extend([0; 0x4000]);
+        let mut cartridge = Cartridge::from_ines(&image([1, 0, 0x04, 0], &data)).unwrap();
+        assert_eq!(cartridge.peek(0x7000), Driven::full(0xaa));
+        assert_eq!(cartridge.peek(0x7200), Driven::full(0x00));
+        assert_eq!(cartridge.read(0x71ff), Driven::full(0xaa));
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt;
+
+use crate::nes::cartridge::Mirroring;
+use crate::nes::TvSystem;
+use crate::Byte;
+
+/// "NES" followed by an MS-DOS end of file
+pub const MAGIC: [Byte; 4] = *b"NES\x1a";
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+const PRG_ROM_UNIT: usize = 16 * 1024;
+const CHR_ROM_UNIT: usize = 8 * 1024;
+const PRG_RAM_UNIT: usize = 8 * 1024;
+/// CHR-RAM fitted to iNES boards without CHR-ROM
+const CHR_RAM_SIZE: usize = 8 * 1024;
+/// RAM sizes in NES 2.0 headers are 64 bytes shifted left by the field
+const RAM_SHIFT_BASE: usize = 64;
+
+/// Error returned when a file is not a loadable iNES image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InesError {
+    /// The file is shorter than the 16-byte header
+    TruncatedHeader { length: usize },
+    /// The file does not start with "NES" and $1A
+    BadMagic { magic: [Byte; 4] },
+    /// An exponent-multiplier ROM size does not fit in memory
+    RomSizeOverflow { exponent: Byte, multiplier: Byte },
+    /// The trainer and ROMs together do not fit in memory
+    ImageSizeOverflow,
+    /// The header announces more trainer and ROM data than the file holds
+    TruncatedData { expected: usize, length: usize },
+    /// The header announces no PRG-ROM, leaving the CPU nothing to run
+    MissingPrgRom,
+    /// No mapper is emulated for the board
+    UnsupportedMapper { mapper: u16, submapper: Byte },
+    /// The mapper needs more PRG-ROM than the image has
+    PrgRomTooSmall { mapper: u16, size: usize, minimum: usize },
+}
+
+impl fmt::Display for InesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InesError::TruncatedHeader { length } => {
+                write!(f, "file of {length} bytes is too short for an iNES header")
+            }
+            InesError::BadMagic { magic } => {
+                write!(f, "not an iNES file, it starts with {magic:02X?}")
+            }
+            InesError::RomSizeOverflow { exponent, multiplier } => {
+                write!(f, "ROM size 2^{exponent} * {multiplier} is too large")
+            }
+            InesError::ImageSizeOverflow => write!(f, "ROM sizes add up to more than fits in memory"),
+            InesError::TruncatedData { expected, length } => {
+                write!(f, "header announces {expected} bytes but the file has {length}")
+            }
+            InesError::MissingPrgRom => write!(f, "header announces no PRG-ROM"),
+            InesError::UnsupportedMapper { mapper, submapper } => {
+                write!(f, "mapper {mapper}.{submapper} is not supported")
+            }
+            InesError::PrgRomTooSmall { mapper, size, minimum } => {
+                write!(f, "mapper {mapper} needs {minimum} bytes of PRG-ROM but the image has {size}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InesError {}
+
+/// CPU and PPU timing the cartridge was made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Runs on consoles of every region
+    MultipleRegion,
+    Dendy,
+}
+
+impl Timing {
+    /// Console the cartridge should run on, NTSC for cartridges made for every region
+    pub fn tv_system(&self) -> TvSystem {
+        match self {
+            Timing::Ntsc | Timing::MultipleRegion => TvSystem::Ntsc,
+            Timing::Pal => TvSystem::Pal,
+            Timing::Dendy => TvSystem::Dendy,
+        }
+    }
+}
+
+/// Machine the cartridge plugs into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleType {
+    /// Nintendo Entertainment System or Famicom
+    #[default]
+    Nes,
+    /// Vs. System arcade board, with the PPU and protection hardware types of NES 2.0 headers
+    VsSystem { ppu: Byte, hardware: Byte },
+    PlayChoice10,
+    /// NES 2.0 extended console type, such as a Famiclone with decimal mode
+    Extended(Byte),
+}
+
+/// Board description from the 16-byte iNES or NES 2.0 header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Whether the header is in the NES 2.0 format rather than plain iNES
+    pub nes2: bool,
+    pub mapper: u16,
+    /// Variant of the mapper, always 0 in iNES headers
+    pub submapper: Byte,
+    pub mirroring: Mirroring,
+    /// The board keeps its PRG-RAM powered by a battery
+    pub battery: bool,
+    /// 512 bytes for $7000-$71FF precede the PRG-ROM
+    pub trainer: bool,
+    pub prg_rom_size: usize,
+    /// Size of the CHR-ROM, 0 when the board has CHR-RAM instead
+    pub chr_rom_size: usize,
+    /// Volatile PRG-RAM
+    pub prg_ram_size: usize,
+    /// Battery-backed PRG-RAM or EEPROM
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub timing: Timing,
+    pub console: ConsoleType,
+    /// Number of miscellaneous ROMs after the CHR-ROM
+    pub misc_roms: Byte,
+    /// Input device the game expects, as numbered by the NES 2.0 specification, 0 if unspecified
+    pub expansion_device: Byte,
+}
+
+impl Header {
+    /// Parses the header at the start of `bytes`
+    pub fn parse(bytes: &[Byte]) -> Result<Header, InesError> {
+        let header: &[Byte; HEADER_SIZE] = bytes
+            .get(..HEADER_SIZE)
+            .and_then(|header| header.try_into().ok())
+            .ok_or(InesError::TruncatedHeader { length: bytes.len() })?;
+        let magic = [header[0], header[1], header[2], header[3]];
+        if magic != MAGIC {
+            return Err(InesError::BadMagic { magic });
+        }
+        match header[7] & 0b1100 {
+            0b1000 => parse_nes2(header),
+            _ => Ok(parse_ines(header)),
+        }
+    }
+
+    /// Size of the whole image the header describes, miscellaneous ROMs excluded
+    pub fn image_size(&self) -> Result<usize, InesError> {
+        let trainer = match self.trainer {
+            true => TRAINER_SIZE,
+            false => 0,
+        };
+        [trainer, self.prg_rom_size, self.chr_rom_size]
+            .into_iter()
+            .try_fold(HEADER_SIZE, usize::checked_add)
+            .ok_or(InesError::ImageSizeOverflow)
+    }
+}
+
+/// Fields iNES and NES 2.0 headers share, with the mapper limited to 8 bits
+fn parse_common(header: &[Byte; HEADER_SIZE]) -> Header {
+    let flags6 = header[6];
+    let flags7 = header[7];
+    let mirroring = match (flags6 & 0b1000 != 0, flags6 & 0b0001 != 0) {
+        (true, _) => Mirroring::FourScreen,
+        (false, false) => Mirroring::Horizontal,
+        (false, true) => Mirroring::Vertical,
+    };
+    Header {
+        nes2: false,
+        mapper: ((flags7 & 0xf0) | (flags6 >> 4)) as u16,
+        submapper: 0,
+        mirroring,
+        battery: flags6 & 0b0010 != 0,
+        trainer: flags6 & 0b0100 != 0,
+        prg_rom_size: header[4] as usize * PRG_ROM_UNIT,
+        chr_rom_size: header[5] as usize * CHR_ROM_UNIT,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+        timing: Timing::Ntsc,
+        console: match flags7 & 0b11 {
+            1 => ConsoleType::VsSystem { ppu: 0, hardware: 0 },
+            2 => ConsoleType::PlayChoice10,
+            _ => ConsoleType::Nes,
+        },
+        misc_roms: 0,
+        expansion_device: 0,
+    }
+}
+
+fn parse_ines(header: &[Byte; HEADER_SIZE]) -> Header {
+    let mut parsed = parse_common(header);
+    // Old dumping tools left their name in bytes 7 to 15, which makes the upper mapper
+    // nibble garbage unless the padding is clean
+    if header[12..].iter().any(|byte| *byte != 0) {
+        parsed.mapper &= 0x0f;
+    }
+    if parsed.chr_rom_size == 0 {
+        parsed.chr_ram_size = CHR_RAM_SIZE;
+    }
+    // A zero size means the 8 KiB most boards with PRG-RAM have, for compatibility
+    let prg_ram_size = header[8].max(1) as usize * PRG_RAM_UNIT;
+    match parsed.battery {
+        true => parsed.prg_nvram_size = prg_ram_size,
+        false => parsed.prg_ram_size = prg_ram_size,
+    }
+    if header[9] & 1 != 0 {
+        parsed.timing = Timing::Pal;
+    }
+    parsed
+}
+
+fn parse_nes2(header: &[Byte; HEADER_SIZE]) -> Result<Header, InesError> {
+    let mut parsed = parse_common(header);
+    parsed.nes2 = true;
+    parsed.mapper |= ((header[8] & 0x0f) as u16) << 8;
+    parsed.submapper = header[8] >> 4;
+    parsed.prg_rom_size = rom_size(header[4], header[9] & 0x0f, PRG_ROM_UNIT)?;
+    parsed.chr_rom_size = rom_size(header[5], header[9] >> 4, CHR_ROM_UNIT)?;
+    parsed.prg_ram_size = ram_size(header[10] & 0x0f);
+    parsed.prg_nvram_size = ram_size(header[10] >> 4);
+    parsed.chr_ram_size = ram_size(header[11] & 0x0f);
+    parsed.chr_nvram_size = ram_size(header[11] >> 4);
+    parsed.timing = match header[12] & 0b11 {
+        0 => Timing::Ntsc,
+        1 => Timing::Pal,
+        2 => Timing::MultipleRegion,
+        _ => Timing::Dendy,
+    };
+    parsed.console = match header[7] & 0b11 {
+        1 => ConsoleType::VsSystem {
+            ppu: header[13] & 0x0f,
+            hardware: header[13] >> 4,
+        },
+        2 => ConsoleType::PlayChoice10,
+        3 => ConsoleType::Extended(header[13] & 0x0f),
+        _ => ConsoleType::Nes,
+    };
+    parsed.misc_roms = header[14] & 0b11;
+    parsed.expansion_device = header[15] & 0x3f;
+    // Exponent-multiplier sizes are each representable but can still add up past usize
+    parsed.image_size()?;
+    Ok(parsed)
+}
+
+/// ROM size from the low byte in the header and the upper nibble from byte 9
+///
+/// An upper nibble of $F switches the low byte to an exponent-multiplier form EEEEEEMM giving
+/// 2^E * (MM * 2 + 1) bytes.
+fn rom_size(low: Byte, high: Byte, unit: usize) -> Result<usize, InesError> {
+    if high != 0x0f {
+        return Ok((((high as usize) << 8) | low as usize) * unit);
+    }
+    let exponent = low >> 2;
+    let multiplier = (low & 0b11) * 2 + 1;
+    1usize
+        .checked_shl(exponent as u32)
+        .and_then(|size| size.checked_mul(multiplier as usize))
+        .ok_or(InesError::RomSizeOverflow { exponent, multiplier })
+}
+
+fn ram_size(shift: Byte) -> usize {
+    match shift {
+        0 => 0,
+        shift => RAM_SHIFT_BASE << shift,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header with `fields` in bytes 4 to 15
+    fn header(fields: [Byte; 12]) -> [Byte; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..].copy_from_slice(&fields);
+        header
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_ines_images() {
+        assert_eq!(Header::parse(&MAGIC), Err(InesError::TruncatedHeader { length: 4 }));
+        let mut bytes = header([0; 12]);
+        bytes[3] = 0;
+        assert_eq!(Header::parse(&bytes), Err(InesError::BadMagic { magic: *b"NES\0" }));
+    }
+
+    #[test]
+    fn parses_ines_headers() {
+        let parsed = Header::parse(&header([2, 0, 0x13, 0x40, 0, 1, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert!(!parsed.nes2);
+        assert_eq!((parsed.mapper, parsed.mirroring, parsed.battery), (0x41, Mirroring::Vertical, true));
+        assert_eq!((parsed.prg_rom_size, parsed.chr_rom_size, parsed.chr_ram_size), (0x8000, 0, 0x2000));
+        assert_eq!((parsed.prg_ram_size, parsed.prg_nvram_size), (0, 0x2000));
+        assert_eq!(parsed.timing, Timing::Pal);
+        assert_eq!(parsed.image_size(), Ok(HEADER_SIZE + 0x8000));
+    }
+
+    #[test]
+    fn ignores_the_upper_mapper_nibble_behind_dirty_padding() {
+        let parsed = Header::parse(&header([1, 1, 0x10, 0x40, 0, 0, 0, 0, b'D', b'i', b's', b'k'])).unwrap();
+        assert_eq!(parsed.mapper, 1);
+    }
+
+    #[test]
+    fn parses_nes2_headers() {
+        let fields = [0x01, 0x02, 0x08, 0x09, 0x51, 0x00, 0x70, 0x07, 0x03, 0x0b, 0x01, 0x02];
+        let parsed = Header::parse(&header(fields)).unwrap();
+        assert!(parsed.nes2);
+        assert_eq!((parsed.mapper, parsed.submapper), (0x100, 5));
+        assert_eq!((parsed.prg_rom_size, parsed.chr_rom_size), (0x4000, 0x4000));
+        assert_eq!((parsed.prg_ram_size, parsed.prg_nvram_size), (0, 0x2000));
+        assert_eq!((parsed.chr_ram_size, parsed.chr_nvram_size), (0x2000, 0));
+        assert_eq!(parsed.timing, Timing::Dendy);
+        assert_eq!(parsed.console, ConsoleType::VsSystem { ppu: 0x0b, hardware: 0 });
+        assert_eq!((parsed.misc_roms, parsed.expansion_device), (1, 2));
+    }
+
+    #[test]
+    fn parses_exponent_multiplier_rom_sizes() {
+        // 2^10 * 3 bytes of PRG-ROM and 2^4 * 1 of CHR-ROM
+        let parsed = Header::parse(&header([0x29, 0x10, 0, 0x08, 0, 0xff, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!((parsed.prg_rom_size, parsed.chr_rom_size), (3 * 1024, 16));
+    }
+
+    #[test]
+    fn rejects_rom_sizes_that_overflow() {
+        let bytes = header([0xff, 0, 0, 0x08, 0, 0x0f, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Header::parse(&bytes), Err(InesError::RomSizeOverflow { exponent: 63, multiplier: 7 }));
+        // 2^63 bytes of PRG-ROM and as many of CHR-ROM each fit but not together
+        let bytes = header([0xfc, 0xfc, 0, 0x08, 0, 0xff, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Header::parse(&bytes), Err(InesError::ImageSizeOverflow));
+    }
+}
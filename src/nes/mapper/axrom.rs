@@ -0,0 +1,101 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::{banked, has_bus_conflicts, Mapper, PRG_BANK_SIZE};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+/// AxROM, mapper 7: a 32 KiB PRG-ROM bank and the single-screen nametable selected by writes to
+/// $8000-$FFFF, unbanked CHR
+pub struct Axrom {
+    memory: CartridgeMemory,
+    bus_conflicts: bool,
+    /// Last value written, bits 0-2 select the PRG bank and bit 4 the nametable
+    bank: Byte,
+}
+
+impl Axrom {
+    pub fn new(memory: CartridgeMemory, submapper: Byte) -> Self {
+        Axrom {
+            memory,
+            // Only AMROM has them, ANROM and AOROM do not
+            bus_conflicts: has_bus_conflicts(submapper, false),
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        if address >= 0x8000 {
+            self.bank = match self.bus_conflicts {
+                true => value & self.cpu_peek(address).value,
+                false => value,
+            };
+        }
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        match address {
+            0x8000..=0xffff => {
+                let offset = banked((self.bank & 0b111) as usize, PRG_BANK_SIZE, address as usize);
+                Driven::full(self.memory.read_prg_rom(offset))
+            }
+            _ => Driven::NONE,
+        }
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.memory.write_chr(address as usize, value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(address as usize)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.bank & 0x10 {
+            0 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four PRG banks holding their number in the low bits and $E0 above
+    fn memory() -> CartridgeMemory {
+        CartridgeMemory {
+            prg_rom: (0..4).flat_map(|bank| vec![0xe0 | bank; PRG_BANK_SIZE]).collect(),
+            prg_ram: Vec::new(),
+            chr: vec![0; 8 * 1024],
+            chr_is_ram: true,
+        }
+    }
+
+    /// PRG bank at $8000 and the nametable selected
+    fn state(axrom: &Axrom) -> (Byte, Mirroring) {
+        (axrom.cpu_peek(0x8000).value & 0b111, axrom.mirroring())
+    }
+
+    #[test]
+    fn selects_the_single_screen_nametable() {
+        let mut axrom = Axrom::new(memory(), 0);
+        assert_eq!(state(&axrom), (0, Mirroring::SingleScreenLower));
+        axrom.cpu_write(0x8000, 0x12);
+        assert_eq!(state(&axrom), (2, Mirroring::SingleScreenUpper));
+        axrom.cpu_write(0x8000, 0x01);
+        assert_eq!(state(&axrom), (1, Mirroring::SingleScreenLower));
+    }
+
+    #[test]
+    fn ands_writes_with_rom_only_when_the_submapper_says_so() {
+        // ANROM and AOROM latch the write as is, AMROM ANDs it with the $E0 at $8000
+        let (latched, anded) = ((2, Mirroring::SingleScreenUpper), (0, Mirroring::SingleScreenLower));
+        for (submapper, expected) in [(0, latched), (1, latched), (2, anded)] {
+            let mut axrom = Axrom::new(memory(), submapper);
+            axrom.cpu_write(0x8000, 0x12);
+            assert_eq!(state(&axrom), expected, "submapper {submapper}");
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::{banked, has_bus_conflicts, Mapper, CHR_BANK_SIZE};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+/// CNROM, mapper 3: PRG-ROM mapped as on NROM, an 8 KiB CHR bank switched by writes to
+/// $8000-$FFFF and fixed mirroring
+pub struct Cnrom {
+    memory: CartridgeMemory,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: Byte,
+}
+
+impl Cnrom {
+    pub fn new(memory: CartridgeMemory, mirroring: Mirroring, submapper: Byte) -> Self {
+        Cnrom {
+            memory,
+            mirroring,
+            bus_conflicts: has_bus_conflicts(submapper, true),
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        if address >= 0x8000 {
+            self.chr_bank = match self.bus_conflicts {
+                true => value & self.cpu_peek(address).value,
+                false => value,
+            };
+        }
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        match address {
+            0x8000..=0xffff => Driven::full(self.memory.read_prg_rom((address - 0x8000) as usize)),
+            _ => Driven::NONE,
+        }
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.memory.write_chr(banked(self.chr_bank as usize, CHR_BANK_SIZE, address as usize), value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(banked(self.chr_bank as usize, CHR_BANK_SIZE, address as usize))
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 32 KiB of $FC PRG-ROM and four CHR banks holding their number
+    fn memory() -> CartridgeMemory {
+        CartridgeMemory {
+            prg_rom: vec![0xfc; 32 * 1024],
+            prg_ram: Vec::new(),
+            chr: (0..4).flat_map(|bank| vec![bank; CHR_BANK_SIZE]).collect(),
+            chr_is_ram: false,
+        }
+    }
+
+    #[test]
+    fn ands_writes_with_rom_unless_the_submapper_rules_bus_conflicts_out() {
+        for (submapper, expected) in [(0, 0), (2, 0), (1, 3)] {
+            let mut cnrom = Cnrom::new(memory(), Mirroring::Horizontal, submapper);
+            cnrom.cpu_write(0x8000, 0x03);
+            assert_eq!(cnrom.ppu_peek(0x0000), expected, "submapper {submapper}");
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::{banked, Mapper, CHR_BANK_SIZE, PRG_BANK_SIZE};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+/// How a board built like GxROM splits its bank register between PRG and CHR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GxromLayout {
+    /// GxROM and MxROM, mapper 66: PRG bank in bits 4-5, CHR bank in bits 0-1
+    Gxrom,
+    /// Color Dreams, mapper 11: PRG bank in bits 0-1, CHR bank in bits 4-7
+    ColorDreams,
+}
+
+/// Board with one register selecting a 32 KiB PRG-ROM bank and an 8 KiB CHR bank, with bus
+/// conflicts and fixed mirroring
+pub struct Gxrom {
+    memory: CartridgeMemory,
+    mirroring: Mirroring,
+    layout: GxromLayout,
+    prg_bank: Byte,
+    chr_bank: Byte,
+}
+
+impl Gxrom {
+    pub fn new(memory: CartridgeMemory, mirroring: Mirroring, layout: GxromLayout) -> Self {
+        Gxrom {
+            memory,
+            mirroring,
+            layout,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Gxrom {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        if address < 0x8000 {
+            return;
+        }
+        let value = value & self.cpu_peek(address).value;
+        (self.prg_bank, self.chr_bank) = match self.layout {
+            GxromLayout::Gxrom => ((value >> 4) & 0b11, value & 0b11),
+            GxromLayout::ColorDreams => (value & 0b11, value >> 4),
+        };
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        match address {
+            0x8000..=0xffff => {
+                let offset = banked(self.prg_bank as usize, PRG_BANK_SIZE, address as usize);
+                Driven::full(self.memory.read_prg_rom(offset))
+            }
+            _ => Driven::NONE,
+        }
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.memory.write_chr(banked(self.chr_bank as usize, CHR_BANK_SIZE, address as usize), value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(banked(self.chr_bank as usize, CHR_BANK_SIZE, address as usize))
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four PRG banks and four CHR banks holding their number in the low bits and $FC above,
+    /// except for $FF at the end of each PRG bank where writes see no bus conflict
+    fn memory() -> CartridgeMemory {
+        let fill = |bank_size: usize| (0..4).flat_map(|bank| vec![0xfc | bank; bank_size]).collect();
+        let mut prg_rom: Vec<Byte> = fill(PRG_BANK_SIZE);
+        for bank in 1..=4 {
+            prg_rom[bank * PRG_BANK_SIZE - 1] = 0xff;
+        }
+        CartridgeMemory {
+            prg_rom,
+            prg_ram: Vec::new(),
+            chr: fill(CHR_BANK_SIZE),
+            chr_is_ram: false,
+        }
+    }
+
+    fn banks(gxrom: &Gxrom) -> (Byte, Byte) {
+        (gxrom.cpu_peek(0x8000).value & 0b11, gxrom.ppu_peek(0) & 0b11)
+    }
+
+    #[test]
+    fn splits_the_register_by_layout() {
+        let mut gxrom = Gxrom::new(memory(), Mirroring::Vertical, GxromLayout::Gxrom);
+        gxrom.cpu_write(0xffff, 0x21);
+        assert_eq!(banks(&gxrom), (2, 1));
+        let mut color_dreams = Gxrom::new(memory(), Mirroring::Vertical, GxromLayout::ColorDreams);
+        color_dreams.cpu_write(0xffff, 0x21);
+        assert_eq!(banks(&color_dreams), (1, 2));
+    }
+
+    #[test]
+    fn ands_writes_with_rom() {
+        // Bank 0 holds $FC, so only bits 4-7 of the write reach the register
+        for (layout, expected) in [(GxromLayout::Gxrom, (3, 0)), (GxromLayout::ColorDreams, (0, 3))] {
+            let mut gxrom = Gxrom::new(memory(), Mirroring::Vertical, layout);
+            gxrom.cpu_write(0x8000, 0x33);
+            assert_eq!(banks(&gxrom), expected, "{layout:?}");
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::{banked, Mapper};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+const PRG_BANK_SIZE: usize = 16 * 1024;
+const CHR_BANK_SIZE: usize = 4 * 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+/// 16 KiB banks in the 256 KiB of PRG-ROM the PRG bank register reaches
+const PRG_BANKS_PER_OUTER_BANK: usize = 16;
+/// Control value at power-on, with the last PRG bank fixed at $C000
+const CONTROL_POWER_ON: Byte = 0b0_11_00;
+
+/// Board an MMC1 sits on, the larger ones reuse the upper CHR bank bits for PRG-ROM and PRG-RAM
+/// banking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mmc1Board {
+    /// SAROM, SKROM, SLROM and friends, where every CHR bank bit addresses CHR
+    Standard,
+    /// 8 KiB CHR-RAM, CHR bank bit 4 disables PRG-RAM
+    Snrom,
+    /// 16 KiB PRG-RAM, CHR bank bit 3 selects its 8 KiB bank
+    Sorom,
+    /// 512 KiB PRG-ROM, CHR bank bit 4 selects its 256 KiB half
+    Surom,
+    /// 512 KiB PRG-ROM and 32 KiB PRG-RAM, CHR bank bit 4 selects the PRG-ROM half and bits 2-3
+    /// the PRG-RAM bank
+    Sxrom,
+    /// SEROM, SHROM and SH1ROM, 32 KiB of PRG-ROM wired past the PRG banking
+    Serom,
+}
+
+impl Mmc1Board {
+    /// Board named by an NES 2.0 submapper, or guessed from the chip sizes for iNES images and
+    /// submapper 0
+    pub fn identify(submapper: Byte, memory: &CartridgeMemory) -> Self {
+        match submapper {
+            1 => return Mmc1Board::Surom,
+            2 => return Mmc1Board::Sorom,
+            3 => return Mmc1Board::Sxrom,
+            5 => return Mmc1Board::Serom,
+            _ => {}
+        }
+        let large_prg_rom = memory.prg_rom.len() > PRG_BANKS_PER_OUTER_BANK * PRG_BANK_SIZE;
+        match memory.prg_ram.len() / 1024 {
+            32 => Mmc1Board::Sxrom,
+            16 => Mmc1Board::Sorom,
+            _ if large_prg_rom => Mmc1Board::Surom,
+            8 if memory.chr_is_ram && memory.chr.len() == 2 * CHR_BANK_SIZE => Mmc1Board::Snrom,
+            _ => Mmc1Board::Standard,
+        }
+    }
+}
+
+/// Nintendo MMC1, mapper 1: registers loaded serially one bit per write, 16 or 32 KiB PRG-ROM
+/// banks, 4 or 8 KiB CHR banks and switchable mirroring
+pub struct Mmc1 {
+    memory: CartridgeMemory,
+    board: Mmc1Board,
+    /// Bits shifted in so far, least significant first
+    shift: Byte,
+    shift_count: u8,
+    control: Byte,
+    chr_banks: [Byte; 2],
+    prg_bank: Byte,
+    /// Which CHR bank register the PPU used last, boards reusing CHR bank bits follow it
+    last_chr_bank: usize,
+    /// CPU cycles counted so far and the cycle of the last serial write
+    cycles: u64,
+    last_write: Option<u64>,
+}
+
+impl Mmc1 {
+    pub fn new(memory: CartridgeMemory, submapper: Byte) -> Self {
+        let board = Mmc1Board::identify(submapper, &memory);
+        Mmc1::with_board(memory, board)
+    }
+
+    pub fn with_board(memory: CartridgeMemory, board: Mmc1Board) -> Self {
+        Mmc1 {
+            memory,
+            board,
+            shift: 0,
+            shift_count: 0,
+            control: CONTROL_POWER_ON,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+            last_chr_bank: 0,
+            cycles: 0,
+            last_write: None,
+        }
+    }
+
+    pub fn board(&self) -> Mmc1Board {
+        self.board
+    }
+
+    fn write_register(&mut self, address: Byte2, value: Byte) {
+        match address {
+            0x8000..=0x9fff => self.control = value,
+            0xa000..=0xbfff => self.chr_banks[0] = value,
+            0xc000..=0xdfff => self.chr_banks[1] = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    fn eight_kib_chr(&self) -> bool {
+        self.control & 0b1_00_00 == 0
+    }
+
+    /// CHR bank register whose upper bits the board reuses for PRG banking
+    fn outer_chr_bank(&self) -> Byte {
+        match self.eight_kib_chr() {
+            true => self.chr_banks[0],
+            false => self.chr_banks[self.last_chr_bank],
+        }
+    }
+
+    /// 256 KiB half of the PRG-ROM on SUROM and SXROM
+    fn prg_outer_bank(&self) -> usize {
+        match self.board {
+            Mmc1Board::Surom | Mmc1Board::Sxrom => (self.outer_chr_bank() as usize >> 4) & 1,
+            _ => 0,
+        }
+    }
+
+    fn prg_ram_bank(&self) -> usize {
+        match self.board {
+            Mmc1Board::Sorom => (self.outer_chr_bank() as usize >> 3) & 1,
+            Mmc1Board::Sxrom => (self.outer_chr_bank() as usize >> 2) & 0b11,
+            _ => 0,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        let disabled_by_chr_bank = self.board == Mmc1Board::Snrom && self.outer_chr_bank() & 0x10 != 0;
+        self.prg_bank & 0x10 == 0 && !disabled_by_chr_bank
+    }
+
+    /// 16 KiB PRG-ROM bank mapped at $8000 or $C000
+    fn prg_rom_bank(&self, address: Byte2) -> usize {
+        let upper = address >= 0xc000;
+        if self.board == Mmc1Board::Serom {
+            return upper as usize;
+        }
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let bank = match ((self.control >> 2) & 0b11, upper) {
+            (0 | 1, _) => (bank & !1) | upper as usize,
+            (2, false) => 0,
+            (2, true) => bank,
+            (_, false) => bank,
+            (_, true) => PRG_BANKS_PER_OUTER_BANK - 1,
+        };
+        self.prg_outer_bank() * PRG_BANKS_PER_OUTER_BANK + bank
+    }
+
+    /// Offset into CHR of a pattern table address
+    fn chr_offset(&self, address: Byte2) -> usize {
+        let table = (address as usize >> 12) & 1;
+        let bank = match self.eight_kib_chr() {
+            true => (self.chr_banks[0] & !1) as usize | table,
+            false => self.chr_banks[table] as usize,
+        };
+        banked(bank, CHR_BANK_SIZE, address as usize)
+    }
+
+    fn prg_ram_offset(&self, address: Byte2) -> usize {
+        banked(self.prg_ram_bank(), PRG_RAM_BANK_SIZE, address as usize)
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        match address {
+            0x6000..=0x7fff if self.prg_ram_enabled() => {
+                let offset = self.prg_ram_offset(address);
+                self.memory.write_prg_ram(offset, value);
+            }
+            0x8000..=0xffff => {
+                // The serial port ignores a write on the cycle after another one, so the two
+                // writes of a read-modify-write instruction only count once
+                let consecutive = self.last_write.is_some_and(|last| self.cycles - last <= 1);
+                self.last_write = Some(self.cycles);
+                if consecutive {
+                    return;
+                }
+                if value & 0x80 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= CONTROL_POWER_ON;
+                    return;
+                }
+                self.shift |= (value & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.write_register(address, self.shift);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        match address {
+            0x8000..=0xffff => {
+                let offset = banked(self.prg_rom_bank(address), PRG_BANK_SIZE, address as usize);
+                Driven::full(self.memory.read_prg_rom(offset))
+            }
+            0x6000..=0x7fff if self.prg_ram_enabled() => {
+                self.memory.read_prg_ram(self.prg_ram_offset(address))
+            }
+            _ => Driven::NONE,
+        }
+    }
+
+    fn ppu_read(&mut self, address: Byte2) -> Byte {
+        self.last_chr_bank = (address as usize >> 12) & 1;
+        self.ppu_peek(address)
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.last_chr_bank = (address as usize >> 12) & 1;
+        let offset = self.chr_offset(address);
+        self.memory.write_chr(offset, value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(self.chr_offset(address))
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn cpu_cycle(&mut self) {
+        self.cycles += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chips with every PRG bank and every CHR bank filled with its number
+    fn memory(prg_banks: usize, chr_banks: usize, prg_ram: usize) -> CartridgeMemory {
+        let fill = |banks: usize, bank_size: usize| {
+            (0..banks).flat_map(|bank| vec![bank as Byte; bank_size]).collect::<Vec<_>>()
+        };
+        CartridgeMemory {
+            prg_rom: fill(prg_banks, PRG_BANK_SIZE),
+            prg_ram: vec![0; prg_ram],
+            chr: fill(chr_banks, CHR_BANK_SIZE),
+            chr_is_ram: false,
+        }
+    }
+
+    /// Writes one bit per write to the serial port, a cycle apart from each other
+    fn load(mmc1: &mut Mmc1, address: Byte2, value: Byte) {
+        for bit in 0..5 {
+            mmc1.cpu_write(address, value >> bit);
+            mmc1.cpu_cycle();
+            mmc1.cpu_cycle();
+        }
+    }
+
+    fn prg_banks(mmc1: &Mmc1) -> (Byte, Byte) {
+        (mmc1.cpu_peek(0x8000).value, mmc1.cpu_peek(0xc000).value)
+    }
+
+    #[test]
+    fn loads_registers_after_five_serial_writes() {
+        let mut mmc1 = Mmc1::new(memory(8, 8, 0x2000), 0);
+        assert_eq!(prg_banks(&mmc1), (0, 7));
+        for bit in 0..4 {
+            mmc1.cpu_write(0xe000, 0b00011 >> bit);
+            mmc1.cpu_cycle();
+            mmc1.cpu_cycle();
+            assert_eq!(prg_banks(&mmc1), (0, 7));
+        }
+        mmc1.cpu_write(0xe000, 0);
+        assert_eq!(prg_banks(&mmc1), (3, 7));
+    }
+
+    #[test]
+    fn bit_7_resets_the_shift_register_and_fixes_the_last_bank() {
+        let mut mmc1 = Mmc1::new(memory(8, 8, 0x2000), 0);
+        load(&mut mmc1, 0x8000, 0b0_00_10);
+        load(&mut mmc1, 0xe000, 2);
+        assert_eq!(prg_banks(&mmc1), (2, 3));
+        mmc1.cpu_write(0x8000, 1);
+        mmc1.cpu_cycle();
+        mmc1.cpu_cycle();
+        mmc1.cpu_write(0x8000, 0x80);
+        mmc1.cpu_cycle();
+        mmc1.cpu_cycle();
+        assert_eq!(prg_banks(&mmc1), (2, 7));
+        // The bit shifted in before the reset is gone
+        load(&mut mmc1, 0xe000, 4);
+        assert_eq!(prg_banks(&mmc1), (4, 7));
+    }
+
+    #[test]
+    fn ignores_a_write_on_the_cycle_after_another() {
+        let mut mmc1 = Mmc1::new(memory(8, 8, 0x2000), 0);
+        // A read-modify-write instruction writes the old value, then the new one a cycle later
+        for bit in 0..5 {
+            mmc1.cpu_write(0xe000, 1 >> bit);
+            mmc1.cpu_cycle();
+            mmc1.cpu_write(0xe000, 0x80);
+            mmc1.cpu_cycle();
+            mmc1.cpu_cycle();
+        }
+        assert_eq!(prg_banks(&mmc1), (1, 7));
+    }
+
+    #[test]
+    fn switches_prg_and_chr_modes_and_mirroring() {
+        let mut mmc1 = Mmc1::new(memory(8, 8, 0x2000), 0);
+        load(&mut mmc1, 0xe000, 5);
+        load(&mut mmc1, 0x8000, 0b0_10_00);
+        assert_eq!(prg_banks(&mmc1), (0, 5));
+        load(&mut mmc1, 0x8000, 0b0_00_11);
+        assert_eq!(prg_banks(&mmc1), (4, 5));
+        assert_eq!(mmc1.mirroring(), Mirroring::Horizontal);
+        load(&mut mmc1, 0xa000, 5);
+        load(&mut mmc1, 0xc000, 2);
+        assert_eq!((mmc1.ppu_peek(0x0000), mmc1.ppu_peek(0x1000)), (4, 5));
+        load(&mut mmc1, 0x8000, 0b1_00_10);
+        assert_eq!((mmc1.ppu_peek(0x0000), mmc1.ppu_peek(0x1000)), (5, 2));
+        assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn disables_prg_ram_through_the_prg_bank_register() {
+        let mut mmc1 = Mmc1::new(memory(8, 8, 0x2000), 0);
+        mmc1.cpu_write(0x6000, 0x42);
+        assert_eq!(mmc1.cpu_peek(0x6000), Driven::full(0x42));
+        load(&mut mmc1, 0xe000, 0x10);
+        assert_eq!(mmc1.cpu_peek(0x6000), Driven::NONE);
+    }
+
+    #[test]
+    fn surom_selects_the_prg_rom_half_with_a_chr_bank_bit() {
+        let mut mmc1 = Mmc1::new(memory(32, 8, 0x2000), 0);
+        assert_eq!(mmc1.board(), Mmc1Board::Surom);
+        assert_eq!(prg_banks(&mmc1), (0, 15));
+        load(&mut mmc1, 0xa000, 0x10);
+        assert_eq!(prg_banks(&mmc1), (16, 31));
+    }
+
+    #[test]
+    fn identifies_boards_from_submappers_and_sizes() {
+        assert_eq!(Mmc1::new(memory(8, 8, 0x8000), 0).board(), Mmc1Board::Sxrom);
+        assert_eq!(Mmc1::new(memory(8, 8, 0x4000), 0).board(), Mmc1Board::Sorom);
+        assert_eq!(Mmc1::new(memory(8, 8, 0x2000), 5).board(), Mmc1Board::Serom);
+    }
+}
@@ -0,0 +1,472 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::{banked, Mapper};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+/// PRG-ROM filling the two banks fixed at the top of the CPU address space
+pub const MIN_PRG_ROM_SIZE: usize = 2 * PRG_BANK_SIZE;
+/// M2 cycles A12 must stay low before a rise clocks the IRQ counter, which keeps the eight
+/// sprite pattern fetches of a scanline from counting more than once
+const A12_FILTER_CYCLES: u64 = 3;
+
+/// Chip revision, they differ in when the IRQ counter raises an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mmc3Revision {
+    /// MMC3B and MMC3C, an IRQ whenever a clock leaves the counter at 0, even when it reloads
+    /// with 0
+    #[default]
+    Sharp,
+    /// MMC3A, an IRQ only when a clock decrements the counter to 0 or a reload requested
+    /// through $C001 loads it with 0
+    Nec,
+}
+
+impl Mmc3Revision {
+    /// Revision named by an NES 2.0 submapper, Sharp unless submapper 4 asks for the MMC3A
+    pub fn identify(submapper: Byte) -> Self {
+        match submapper {
+            4 => Mmc3Revision::Nec,
+            _ => Mmc3Revision::Sharp,
+        }
+    }
+}
+
+/// Nintendo MMC3, mapper 4: 8 KiB PRG-ROM banks, 2 and 1 KiB CHR banks, switchable mirroring
+/// and a scanline IRQ counter clocked by rises of PPU address line A12
+pub struct Mmc3 {
+    memory: CartridgeMemory,
+    revision: Mmc3Revision,
+    /// Whether the board wires its own nametable RAM, leaving the mirroring register unused
+    four_screen: bool,
+    /// Bank register the next $8001 write goes to, the PRG mode in bit 6 and CHR inversion in
+    /// bit 7
+    bank_select: Byte,
+    /// R0 and R1 select 2 KiB CHR banks, R2-R5 1 KiB CHR banks, R6 and R7 8 KiB PRG banks
+    banks: [Byte; 8],
+    mirroring: Mirroring,
+    /// PRG-RAM enable in bit 7 and write protect in bit 6
+    prg_ram_protect: Byte,
+    irq_latch: Byte,
+    irq_counter: Byte,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    /// Level of A12 on the last PPU address and the cycle it last went low
+    a12: bool,
+    a12_fell: u64,
+    /// CPU cycles counted so far, the clock of the A12 filter
+    cycles: u64,
+}
+
+impl Mmc3 {
+    pub fn new(memory: CartridgeMemory, mirroring: Mirroring, submapper: Byte) -> Self {
+        Mmc3::with_revision(memory, mirroring, Mmc3Revision::identify(submapper))
+    }
+
+    pub fn with_revision(memory: CartridgeMemory, mirroring: Mirroring, revision: Mmc3Revision) -> Self {
+        Mmc3 {
+            memory,
+            revision,
+            four_screen: mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            banks: [0; 8],
+            mirroring,
+            // Undefined at power-on, enabled so games that never write $A001 keep their RAM
+            prg_ram_protect: 0x80,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            a12_fell: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn revision(&self) -> Mmc3Revision {
+        self.revision
+    }
+
+    fn write_register(&mut self, address: Byte2, value: Byte) {
+        match (address & 0xe000, address & 1) {
+            (0x8000, 0) => self.bank_select = value,
+            (0x8000, _) => self.banks[(self.bank_select & 0b111) as usize] = value,
+            (0xa000, 0) if !self.four_screen => {
+                self.mirroring = match value & 1 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                };
+            }
+            (0xa000, 0) => {}
+            (0xa000, _) => self.prg_ram_protect = value,
+            (0xc000, 0) => self.irq_latch = value,
+            (0xc000, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (_, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (_, _) => self.irq_enabled = true,
+        }
+    }
+
+    /// 8 KiB PRG-ROM bank mapped at `address`
+    fn prg_rom_bank(&self, address: Byte2) -> usize {
+        // Smaller PRG-ROM than the loader accepts repeats its only bank
+        let last = (self.memory.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1);
+        let swappable = (self.banks[6] & 0x3f) as usize;
+        match ((address >> 13) & 0b11, self.bank_select & 0x40 != 0) {
+            (0, false) | (2, true) => swappable,
+            (0, true) | (2, false) => last.saturating_sub(1),
+            (1, _) => (self.banks[7] & 0x3f) as usize,
+            _ => last,
+        }
+    }
+
+    /// Offset into CHR of a pattern table address, the 2 KiB banks sit in the upper pattern
+    /// table when CHR is inverted
+    fn chr_offset(&self, address: Byte2) -> usize {
+        let address = match self.bank_select & 0x80 {
+            0 => address,
+            _ => address ^ 0x1000,
+        };
+        let slot = (address as usize >> 10) & 0b111;
+        let bank = match slot {
+            0..=3 => (self.banks[slot >> 1] & !1) as usize | (slot & 1),
+            _ => self.banks[slot - 2] as usize,
+        };
+        banked(bank, CHR_BANK_SIZE, address as usize)
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_ram_protect & 0x80 != 0
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_enabled() && self.prg_ram_protect & 0x40 == 0
+    }
+
+    /// Follows A12 on the PPU address bus, clocking the IRQ counter on a rise after it stayed
+    /// low long enough
+    fn watch_a12(&mut self, address: Byte2) {
+        let a12 = address & 0x1000 != 0;
+        match (self.a12, a12) {
+            (false, true) if self.cycles - self.a12_fell >= A12_FILTER_CYCLES => self.clock_irq_counter(),
+            (true, false) => self.a12_fell = self.cycles,
+            _ => {}
+        }
+        self.a12 = a12;
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let previous = self.irq_counter;
+        let reloaded = self.irq_reload;
+        match previous == 0 || reloaded {
+            true => self.irq_counter = self.irq_latch,
+            false => self.irq_counter -= 1,
+        }
+        self.irq_reload = false;
+        let raise = match self.revision {
+            Mmc3Revision::Sharp => self.irq_counter == 0,
+            Mmc3Revision::Nec => self.irq_counter == 0 && (previous != 0 || reloaded),
+        };
+        if raise && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        match address {
+            0x6000..=0x7fff if self.prg_ram_writable() => {
+                self.memory.write_prg_ram(address as usize - 0x6000, value);
+            }
+            0x8000..=0xffff => self.write_register(address, value),
+            _ => {}
+        }
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        match address {
+            0x8000..=0xffff => {
+                let offset = banked(self.prg_rom_bank(address), PRG_BANK_SIZE, address as usize);
+                Driven::full(self.memory.read_prg_rom(offset))
+            }
+            0x6000..=0x7fff if self.prg_ram_enabled() => {
+                self.memory.read_prg_ram(address as usize - 0x6000)
+            }
+            _ => Driven::NONE,
+        }
+    }
+
+    fn ppu_read(&mut self, address: Byte2) -> Byte {
+        self.watch_a12(address);
+        self.ppu_peek(address)
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.watch_a12(address);
+        let offset = self.chr_offset(address);
+        self.memory.write_chr(offset, value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(self.chr_offset(address))
+    }
+
+    fn ppu_address(&mut self, address: Byte2) {
+        self.watch_a12(address);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_cycle(&mut self) {
+        self.cycles += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::Cpu;
+    use crate::nes::bus::NesBus;
+    use crate::nes::cartridge::Cartridge;
+    use crate::nes::ines::{InesError, MAGIC};
+
+    /// 128 KiB of PRG-ROM and 64 KiB of CHR-ROM with every bank filled with its number
+    fn memory() -> CartridgeMemory {
+        let fill = |banks: usize, bank_size: usize| {
+            (0..banks).flat_map(|bank| vec![bank as Byte; bank_size]).collect::<Vec<_>>()
+        };
+        CartridgeMemory {
+            prg_rom: fill(16, PRG_BANK_SIZE),
+            prg_ram: vec![0; 0x2000],
+            chr: fill(64, CHR_BANK_SIZE),
+            chr_is_ram: false,
+        }
+    }
+
+    fn prg_banks(mmc3: &Mmc3) -> [Byte; 4] {
+        [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mmc3.cpu_peek(address).value)
+    }
+
+    fn chr_banks(mmc3: &Mmc3) -> Vec<Byte> {
+        (0..8).map(|slot| mmc3.ppu_peek(slot * 0x400)).collect()
+    }
+
+    fn set_bank(mmc3: &mut Mmc3, bank_select: Byte, bank: Byte) {
+        mmc3.cpu_write(0x8000, bank_select);
+        mmc3.cpu_write(0x8001, bank);
+    }
+
+    /// PPU fetches of a rendered scanline with backgrounds at $0000 and sprites at $1000, A12
+    /// rising on each of the eight sprite fetches
+    fn scanline(mmc3: &mut Mmc3) {
+        for _ in 0..80 {
+            mmc3.cpu_cycle();
+            mmc3.ppu_address(0x2000);
+            mmc3.ppu_address(0x0000);
+        }
+        for sprite in 0..8 {
+            mmc3.ppu_address(0x2000);
+            mmc3.ppu_address(0x1000 | sprite << 4);
+        }
+        for _ in 0..34 {
+            mmc3.cpu_cycle();
+        }
+    }
+
+    /// Scanlines out of `count` after which the IRQ was raised, acknowledging it each time
+    fn irq_scanlines(mmc3: &mut Mmc3, count: usize) -> Vec<usize> {
+        let mut raised = Vec::new();
+        for line in 0..count {
+            scanline(mmc3);
+            if mmc3.irq() {
+                raised.push(line);
+                mmc3.cpu_write(0xe000, 0);
+                mmc3.cpu_write(0xe001, 0);
+            }
+        }
+        raised
+    }
+
+    #[test]
+    fn switches_prg_banks_in_both_modes() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 0);
+        set_bank(&mut mmc3, 6, 3);
+        set_bank(&mut mmc3, 7, 5);
+        assert_eq!(prg_banks(&mmc3), [3, 5, 14, 15]);
+        mmc3.cpu_write(0x8000, 0x40);
+        assert_eq!(prg_banks(&mmc3), [14, 5, 3, 15]);
+    }
+
+    #[test]
+    fn swaps_pattern_tables_when_chr_is_inverted() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 0);
+        for (register, bank) in [(0, 9), (1, 20), (2, 40), (3, 41), (4, 42), (5, 43)] {
+            set_bank(&mut mmc3, register, bank);
+        }
+        assert_eq!(chr_banks(&mmc3), [8, 9, 20, 21, 40, 41, 42, 43]);
+        mmc3.cpu_write(0x8000, 0x80);
+        assert_eq!(chr_banks(&mmc3), [40, 41, 42, 43, 8, 9, 20, 21]);
+    }
+
+    #[test]
+    fn switches_mirroring_unless_the_board_has_four_screens() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 0);
+        mmc3.cpu_write(0xa000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::FourScreen, 0);
+        mmc3.cpu_write(0xa000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn protects_prg_ram() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 0);
+        mmc3.cpu_write(0x6000, 1);
+        mmc3.cpu_write(0xa001, 0xc0);
+        mmc3.cpu_write(0x6000, 2);
+        assert_eq!(mmc3.cpu_peek(0x6000), Driven::full(1));
+        mmc3.cpu_write(0xa001, 0x00);
+        assert_eq!(mmc3.cpu_peek(0x6000), Driven::NONE);
+    }
+
+    #[test]
+    fn raises_the_irq_every_latch_plus_one_scanlines() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 0);
+        mmc3.cpu_write(0xc000, 2);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe001, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 7), [2, 5]);
+        // Disabling also acknowledges
+        scanline(&mut mmc3);
+        scanline(&mut mmc3);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xe000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn filters_a12_rises_closer_than_three_cycles() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 0);
+        mmc3.cpu_write(0xc000, 1);
+        mmc3.cpu_write(0xe001, 0);
+        // Each rise comes two cycles after the fall before it and never clocks the counter
+        for _ in 0..20 {
+            mmc3.ppu_address(0x1000);
+            mmc3.ppu_address(0x0000);
+            mmc3.cpu_cycle();
+            mmc3.cpu_cycle();
+        }
+        // Three cycles are enough, the first clock loads the latch and the second reaches 0
+        for raised in [false, true] {
+            mmc3.cpu_cycle();
+            mmc3.ppu_address(0x1000);
+            assert_eq!(mmc3.irq(), raised);
+            mmc3.ppu_address(0x0000);
+            for _ in 0..3 {
+                mmc3.cpu_cycle();
+            }
+        }
+    }
+
+    #[test]
+    fn sharp_raises_the_irq_on_every_clock_with_a_latch_of_zero() {
+        let mut mmc3 = Mmc3::with_revision(memory(), Mirroring::Vertical, Mmc3Revision::Sharp);
+        mmc3.cpu_write(0xc000, 0);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe001, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 4), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn nec_raises_the_irq_with_a_latch_of_zero_only_after_a_reload_request() {
+        let mut mmc3 = Mmc3::new(memory(), Mirroring::Vertical, 4);
+        assert_eq!(mmc3.revision(), Mmc3Revision::Nec);
+        mmc3.cpu_write(0xc000, 0);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe001, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 4), [0]);
+        // Both revisions raise it when the counter decrements to 0
+        mmc3.cpu_write(0xc000, 1);
+        mmc3.cpu_write(0xc001, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 4), [1, 3]);
+    }
+
+    #[test]
+    fn does_not_panic_on_a_single_prg_bank() {
+        let memory = CartridgeMemory {
+            prg_rom: vec![0xea; PRG_BANK_SIZE],
+            ..memory()
+        };
+        let mut mmc3 = Mmc3::new(memory, Mirroring::Vertical, 0);
+        mmc3.cpu_write(0x8000, 0x40);
+        assert_eq!(prg_banks(&mmc3), [0xea; 4]);
+    }
+
+    /// MMC3 image whose last PRG bank clears I and spins, the IRQ handler at $E100 spins too
+    fn image(prg_rom_units: Byte) -> Vec<Byte> {
+        let mut image = MAGIC.to_vec();
+        image.extend([prg_rom_units, 1, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut prg_rom = vec![0; prg_rom_units as usize * 0x4000];
+        let last = prg_rom.len() - PRG_BANK_SIZE;
+        prg_rom[last..last + 4].copy_from_slice(&[0x58, 0x4c, 0x01, 0xe0]);
+        prg_rom[last + 0x100..last + 0x103].copy_from_slice(&[0x4c, 0x00, 0xe1]);
+        prg_rom[last + 0x1ffa..].copy_from_slice(&[0x00, 0xe1, 0x00, 0xe0, 0x00, 0xe1]);
+        image.extend(prg_rom);
+        image.extend([0; 0x2000]);
+        image
+    }
+
+    #[test]
+    fn loader_rejects_prg_rom_smaller_than_the_fixed_banks() {
+        // NES 2.0 header announcing 2^13 * 1 bytes in the exponent-multiplier form
+        let mut image = image(1);
+        image[4] = 13 << 2;
+        image[7] = 0x08;
+        image[9] = 0x0f;
+        let error = Cartridge::from_ines(&image).err();
+        let expected = InesError::PrgRomTooSmall { mapper: 4, size: 0x2000, minimum: MIN_PRG_ROM_SIZE };
+        assert_eq!(error, Some(expected));
+    }
+
+    #[test]
+    fn irq_reaches_the_cpu_through_the_bus() {
+        let mut bus = NesBus::new();
+        bus.insert_cartridge(Cartridge::from_ines(&image(2)).unwrap());
+        let mut cpu = Cpu::power_on(bus);
+        cpu.bus_mut().write(0xc000, 1);
+        cpu.bus_mut().write(0xc001, 0);
+        cpu.bus_mut().write(0xe001, 0);
+        for line in 0..2 {
+            assert!(!cpu.bus().irq(), "scanline {line}");
+            // The PPU runs three dots per CPU cycle, a scanline takes about 114 cycles
+            while cpu.cycles() < (line + 1) * 114 {
+                cpu.step().unwrap();
+                let address = match cpu.cycles() % 114 < 100 {
+                    true => 0x0000,
+                    false => 0x1000,
+                };
+                cpu.bus_mut().cartridge_mut().unwrap().mapper_mut().ppu_address(address);
+            }
+        }
+        assert!(cpu.bus().irq());
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers().p, 0xe100);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::ines::{Header, InesError};
+use crate::nes::mapper::gxrom::GxromLayout;
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+pub mod axrom;
+pub mod cnrom;
+pub mod gxrom;
+pub mod mmc1;
+pub mod mmc3;
+pub mod nrom;
+pub mod uxrom;
+
+/// 32 KiB PRG-ROM bank of the discrete-logic boards
+const PRG_BANK_SIZE: usize = 32 * 1024;
+/// 8 KiB CHR bank of the discrete-logic boards
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+/// Logic of a cartridge board, sitting between the console buses and the ROM and RAM chips
+///
+/// The CPU side sees $4020-$FFFF, the PPU side the pattern tables at $0000-$1FFF.
+pub trait Mapper {
+    fn cpu_read(&mut self, address: Byte2) -> Driven {
+        self.cpu_peek(address)
+    }
+
+    fn cpu_write(&mut self, address: Byte2, value: Byte);
+
+    /// Value a CPU read would return, without its side effects
+    fn cpu_peek(&self, address: Byte2) -> Driven;
+
+    fn ppu_read(&mut self, address: Byte2) -> Byte {
+        self.ppu_peek(address)
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte);
+
+    /// Value a PPU read would return, without its side effects
+    fn ppu_peek(&self, address: Byte2) -> Byte;
+
+    /// Called with every address the PPU puts on its bus, nametable and palette accesses
+    /// included, for boards watching its address lines
+    fn ppu_address(&mut self, _address: Byte2) {}
+
+    /// Current arrangement of the nametables
+    fn mirroring(&self) -> Mirroring;
+
+    /// Whether the board holds the CPU IRQ line asserted
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Called once per CPU cycle, for boards counting M2 cycles
+    fn cpu_cycle(&mut self) {}
+
+    /// Called once per rendered scanline, for boards counting scanlines without watching the PPU
+    /// address bus
+    fn scanline(&mut self) {}
+}
+
+/// Builds the mapper the header asks for around the chips of the cartridge
+pub fn create_mapper(header: &Header, memory: CartridgeMemory) -> Result<Box<dyn Mapper>, InesError> {
+    match header.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(memory, header.mirroring))),
+        1 => Ok(Box::new(mmc1::Mmc1::new(memory, header.submapper))),
+        2 => Ok(Box::new(uxrom::Uxrom::new(memory, header.mirroring, header.submapper))),
+        3 => Ok(Box::new(cnrom::Cnrom::new(memory, header.mirroring, header.submapper))),
+        4 if memory.prg_rom.len() < mmc3::MIN_PRG_ROM_SIZE => Err(InesError::PrgRomTooSmall {
+            mapper: header.mapper,
+            size: memory.prg_rom.len(),
+            minimum: mmc3::MIN_PRG_ROM_SIZE,
+        }),
+        4 => Ok(Box::new(mmc3::Mmc3::new(memory, header.mirroring, header.submapper))),
+        7 => Ok(Box::new(axrom::Axrom::new(memory, header.submapper))),
+        11 => Ok(Box::new(gxrom::Gxrom::new(memory, header.mirroring, GxromLayout::ColorDreams))),
+        66 => Ok(Box::new(gxrom::Gxrom::new(memory, header.mirroring, GxromLayout::Gxrom))),
+        mapper => Err(InesError::UnsupportedMapper {
+            mapper,
+            submapper: header.submapper,
+        }),
+    }
+}
+
+/// Offset into a chip of byte `offset` within bank `bank` of `bank_size` bytes, the chip
+/// wraps bank numbers beyond its size as the unconnected address lines do
+pub fn banked(bank: usize, bank_size: usize, offset: usize) -> usize {
+    bank * bank_size + offset % bank_size
+}
+
+/// Whether a discrete-logic board lets ROM and CPU drive the data bus together on writes, so
+/// the value latched is their AND
+///
+/// On mappers 2, 3 and 7, NES 2.0 submapper 1 rules them out and 2 confirms them, otherwise the
+/// board decides.
+pub fn has_bus_conflicts(submapper: Byte, default: bool) -> bool {
+    match submapper {
+        1 => false,
+        2 => true,
+        _ => default,
+    }
+}
@@ -0,0 +1,47 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::Mapper;
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+/// NROM, mapper 0: no banking, 16 KiB of PRG-ROM mirrored on NROM-128 or 32 KiB on NROM-256,
+/// 8 KiB of CHR and mirroring fixed by solder pads
+///
+/// PRG-RAM at $6000-$7FFF only exists on a few boards like Family BASIC.
+pub struct Nrom {
+    memory: CartridgeMemory,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(memory: CartridgeMemory, mirroring: Mirroring) -> Self {
+        Nrom { memory, mirroring }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        if let 0x6000..=0x7fff = address {
+            self.memory.write_prg_ram((address - 0x6000) as usize, value);
+        }
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        match address {
+            0x8000..=0xffff => Driven::full(self.memory.read_prg_rom((address - 0x8000) as usize)),
+            0x6000..=0x7fff => self.memory.read_prg_ram((address - 0x6000) as usize),
+            _ => Driven::NONE,
+        }
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.memory.write_chr(address as usize, value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(address as usize)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,85 @@
+use crate::nes::cartridge::{CartridgeMemory, Mirroring};
+use crate::nes::mapper::{banked, has_bus_conflicts, Mapper};
+use crate::nes::open_bus::Driven;
+use crate::{Byte, Byte2};
+
+const PRG_BANK_SIZE: usize = 16 * 1024;
+
+/// UxROM, mapper 2: a 16 KiB PRG-ROM bank switched at $8000 with the last bank fixed at $C000,
+/// unbanked CHR and fixed mirroring
+pub struct Uxrom {
+    memory: CartridgeMemory,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_bank: Byte,
+}
+
+impl Uxrom {
+    pub fn new(memory: CartridgeMemory, mirroring: Mirroring, submapper: Byte) -> Self {
+        Uxrom {
+            memory,
+            mirroring,
+            // UNROM and UOROM have them, a submapper can say otherwise
+            bus_conflicts: has_bus_conflicts(submapper, true),
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_write(&mut self, address: Byte2, value: Byte) {
+        if address >= 0x8000 {
+            self.prg_bank = match self.bus_conflicts {
+                true => value & self.cpu_peek(address).value,
+                false => value,
+            };
+        }
+    }
+
+    fn cpu_peek(&self, address: Byte2) -> Driven {
+        let bank = match address {
+            0x8000..=0xbfff => self.prg_bank as usize,
+            // PRG-ROM under 16 KiB leaves bank 0 as the last one
+            0xc000..=0xffff => (self.memory.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1),
+            _ => return Driven::NONE,
+        };
+        Driven::full(self.memory.read_prg_rom(banked(bank, PRG_BANK_SIZE, address as usize)))
+    }
+
+    fn ppu_write(&mut self, address: Byte2, value: Byte) {
+        self.memory.write_chr(address as usize, value);
+    }
+
+    fn ppu_peek(&self, address: Byte2) -> Byte {
+        self.memory.read_chr(address as usize)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four PRG banks holding their number in the low bits and $FC above
+    fn memory() -> CartridgeMemory {
+        CartridgeMemory {
+            prg_rom: (0..4).flat_map(|bank| vec![0xfc | bank; PRG_BANK_SIZE]).collect(),
+            prg_ram: Vec::new(),
+            chr: vec![0; 8 * 1024],
+            chr_is_ram: true,
+        }
+    }
+
+    #[test]
+    fn ands_writes_with_rom_unless_the_submapper_rules_bus_conflicts_out() {
+        for (submapper, expected) in [(0, 0), (2, 0), (1, 3)] {
+            let mut uxrom = Uxrom::new(memory(), Mirroring::Vertical, submapper);
+            uxrom.cpu_write(0x8000, 0x03);
+            assert_eq!(uxrom.cpu_peek(0x8000).value & 0b11, expected, "submapper {submapper}");
+            assert_eq!(uxrom.cpu_peek(0xc000).value & 0b11, 3, "submapper {submapper}");
+        }
+    }
+}
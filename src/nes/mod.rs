@@ -0,0 +1,25 @@
+pub mod bus;
+pub mod cartridge;
+pub mod ines;
+pub mod mapper;
+pub mod open_bus;
+
+/// Television standard of a console, which sets its clock rates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TvSystem {
+    #[default]
+    Ntsc,
+    Pal,
+    /// PAL Famiclone running the CPU close to NTSC speed
+    Dendy,
+}
+
+impl TvSystem {
+    pub fn cpu_clock_hz(&self) -> u64 {
+        match self {
+            TvSystem::Ntsc => 1_789_773,
+            TvSystem::Pal => 1_662_607,
+            TvSystem::Dendy => 1_773_448,
+        }
+    }
+}
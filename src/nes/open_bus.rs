@@ -0,0 +1,112 @@
+use crate::nes::TvSystem;
+use crate::Byte;
+
+/// How long a bit of the PPU I/O latch holds its value
+const PPU_LATCH_DECAY_MILLISECONDS: u64 = 600;
+
+/// CPU cycles a bit of the PPU I/O latch holds its value on the given console
+pub fn ppu_latch_decay_cycles(tv_system: TvSystem) -> u64 {
+    tv_system.cpu_clock_hz() * PPU_LATCH_DECAY_MILLISECONDS / 1000
+}
+
+/// Value a device drives onto a data bus, bits outside `mask` are left floating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Driven {
+    pub value: Byte,
+    pub mask: Byte,
+}
+
+impl Driven {
+    /// Nothing drives the bus
+    pub const NONE: Driven = Driven { value: 0, mask: 0 };
+
+    pub const fn full(value: Byte) -> Self {
+        Driven { value, mask: 0xff }
+    }
+
+    pub const fn partial(value: Byte, mask: Byte) -> Self {
+        Driven { value: value & mask, mask }
+    }
+
+    /// Byte read back with the floating bits taken from what the bus last held
+    pub const fn resolve(self, open_bus: Byte) -> Byte {
+        (self.value & self.mask) | (open_bus & !self.mask)
+    }
+}
+
+/// Latch whose bits fade to 0 unless driven again, like the I/O data bus of the PPU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecayingLatch {
+    value: Byte,
+    /// Cycle on which each bit was last driven
+    refreshed: [u64; 8],
+    /// Cycles a bit holds its value, `None` when bits never decay
+    decay: Option<u64>,
+}
+
+impl DecayingLatch {
+    pub fn new(decay: Option<u64>) -> Self {
+        DecayingLatch {
+            value: 0,
+            refreshed: [0; 8],
+            decay,
+        }
+    }
+
+    pub fn set_decay(&mut self, decay: Option<u64>) {
+        self.decay = decay;
+    }
+
+    /// Latches the driven bits on cycle `now`, the others keep decaying
+    pub fn drive(&mut self, driven: Driven, now: u64) {
+        self.value = driven.resolve(self.value);
+        for (bit, refreshed) in self.refreshed.iter_mut().enumerate() {
+            if driven.mask & (1 << bit) != 0 {
+                *refreshed = now;
+            }
+        }
+    }
+
+    /// Contents on cycle `now`, with the bits that have decayed read as 0
+    pub fn value(&self, now: u64) -> Byte {
+        let Some(decay) = self.decay else {
+            return self.value;
+        };
+        let held = self.refreshed.iter().enumerate().fold(0, |held, (bit, refreshed)| {
+            match now.saturating_sub(*refreshed) < decay {
+                true => held | (1 << bit),
+                false => held,
+            }
+        });
+        self.value & held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undriven_bits_keep_the_open_bus_value() {
+        assert_eq!(Driven::NONE.resolve(0x5a), 0x5a);
+        assert_eq!(Driven::full(0x12).resolve(0x5a), 0x12);
+        assert_eq!(Driven::partial(0xff, 0xe0).resolve(0x5a), 0xfa);
+    }
+
+    #[test]
+    fn latch_bits_fade_after_the_decay_deadline() {
+        let mut latch = DecayingLatch::new(Some(100));
+        latch.drive(Driven::full(0xff), 0);
+        latch.drive(Driven::partial(0x0f, 0x0f), 50);
+        assert_eq!(latch.value(99), 0xff);
+        assert_eq!(latch.value(100), 0x0f);
+        assert_eq!(latch.value(150), 0x00);
+    }
+
+    #[test]
+    fn latch_without_decay_never_fades() {
+        let mut latch = DecayingLatch::new(None);
+        latch.drive(Driven::full(0xa5), 0);
+        assert_eq!(latch.value(u64::MAX), 0xa5);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::Byte;
+
+/// Contents RAM starts with at power-on, which real hardware leaves undefined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamFill {
+    /// Every byte $00
+    #[default]
+    Zero,
+    /// Every byte $FF
+    Ones,
+    /// Four bytes of $00 then four of $FF, the pattern many emulators use
+    Alternating,
+    /// Pseudo-random bytes, the same seed always gives the same contents
+    Random(u64),
+}
+
+impl RamFill {
+    pub fn fill(&self, ram: &mut [Byte]) {
+        match *self {
+            RamFill::Zero => ram.fill(0x00),
+            RamFill::Ones => ram.fill(0xff),
+            RamFill::Alternating => {
+                for (address, byte) in ram.iter_mut().enumerate() {
+                    *byte = match address & 0b100 {
+                        0 => 0x00,
+                        _ => 0xff,
+                    };
+                }
+            }
+            RamFill::Random(seed) => {
+                let mut random = SplitMix64(seed);
+                for chunk in ram.chunks_mut(8) {
+                    let bytes = random.next().to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+/// SplitMix64 generator, small and good enough for filling RAM reproducibly
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::Cpu;
+    use crate::nes::bus::NesBus;
+
+    fn filled(fill: RamFill) -> [Byte; 0x800] {
+        let mut ram = [0x5a; 0x800];
+        fill.fill(&mut ram);
+        ram
+    }
+
+    #[test]
+    fn fills_the_fixed_patterns() {
+        assert!(filled(RamFill::Zero).iter().all(|byte| *byte == 0x00));
+        assert!(filled(RamFill::Ones).iter().all(|byte| *byte == 0xff));
+        assert_eq!(filled(RamFill::Alternating)[..12], [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+        assert_eq!(filled(RamFill::Alternating)[0x7fc..], [0xff; 4]);
+    }
+
+    #[test]
+    fn random_fill_depends_only_on_the_seed() {
+        assert_eq!(filled(RamFill::Random(1)), filled(RamFill::Random(1)));
+        assert_ne!(filled(RamFill::Random(1)), filled(RamFill::Random(2)));
+        // A length that is not a multiple of eight still gets the same leading bytes
+        let mut short = [0; 13];
+        RamFill::Random(1).fill(&mut short);
+        assert_eq!(short, filled(RamFill::Random(1))[..13]);
+    }
+
+    #[test]
+    fn power_on_sets_up_the_stack_and_reset_keeps_ram() {
+        let mut cpu = Cpu::power_on(NesBus::with_ram_fill(RamFill::Random(7)));
+        assert_eq!(cpu.bus().ram(), filled(RamFill::Random(7)));
+        assert_eq!(cpu.registers().s, 0xfd);
+        assert!(cpu.registers().status.get_i());
+        cpu.registers_mut().status.replace_i(false);
+        cpu.bus_mut().write(0x0123, 0x42);
+        cpu.reset();
+        assert_eq!(cpu.bus().peek(0x0123), 0x42);
+        assert_eq!(cpu.registers().s, 0xfa);
+        assert!(cpu.registers().status.get_i());
+    }
+}